@@ -1,42 +1,507 @@
+use super::const_eval::Value;
+use super::token::Span;
+use super::types::Type;
+use std::fmt;
+use std::ops::Range;
+
 pub type Ident = String;
+// A loop label, without the leading `'`.
+pub type Label = String;
 
+// The linked modules of a program. The items of every module other than the
+// root are named by their path, so `f` in `a/b.sd` is `a::b::f`.
 #[derive(Debug)]
 pub struct Program {
-    pub stmt: Stmt,
+    pub items: Vec<Item>,
+    pub modules: Vec<Module>,
+}
+
+// A source file or shard of the program, whose items are `items[range]` of
+// the program. The path of the root module is empty.
+#[derive(Debug)]
+pub struct Module {
+    pub path: Ident,
+    pub items: Range<usize>,
+    // The name of the shard, if the module is one. Every shard is compiled
+    // to a wasm module of its own, and the other modules to the main one.
+    pub shard: Option<Ident>,
+    // The functions of other shards the module refers to, imported by its
+    // wasm module. Filled in by the type checker.
+    pub imports: Vec<Ident>,
+    // The constants and external functions of other modules it refers to,
+    // which its wasm module declares or imports as well. Filled in by the
+    // type checker.
+    pub consts: Vec<Ident>,
+    pub externs: Vec<Ident>,
+}
+
+impl Module {
+    // The name the item `name` of the module is linked under.
+    pub fn qualify(&self, name: &str) -> Ident {
+        match self.path.as_str() {
+            "" => name.to_string(),
+            path => format!("{}::{}", path, name),
+        }
+    }
+
+    // The name of the item linked as `name` within the module.
+    pub fn unqualify<'a>(&self, name: &'a str) -> &'a str {
+        match self.path.as_str() {
+            "" => name,
+            path => &name[path.len() + 2..],
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Item {
+    Fn(FnDecl),
+    Extern(ExternFn),
+    Const(ConstDecl),
+    Struct(StructDecl),
+    Enum(EnumDecl),
+    Alias(TypeAlias),
+    Impl(ImplDecl),
+    Use(UseDecl),
+    Shard(ShardDecl),
+}
+
+// Declarations keep their `///` comment, one line per line, for tools such
+// as documentation generators. The compiler itself never reads it.
+#[allow(dead_code)]
+pub trait Documented {
+    fn doc(&self) -> Option<&str>;
+}
+
+impl Documented for Item {
+    fn doc(&self) -> Option<&str> {
+        let doc = match self {
+            Item::Fn(decl) => &decl.doc,
+            Item::Extern(ext) => &ext.doc,
+            Item::Const(decl) => &decl.doc,
+            Item::Struct(decl) => &decl.doc,
+            Item::Enum(decl) => &decl.doc,
+            Item::Alias(decl) => &decl.doc,
+            Item::Impl(block) => &block.doc,
+            Item::Use(decl) => &decl.doc,
+            Item::Shard(decl) => &decl.doc,
+        };
+        doc.as_deref()
+    }
+}
+
+impl Documented for Stmt {
+    fn doc(&self) -> Option<&str> {
+        match self {
+            Stmt::Decl(decl) => decl.doc.as_deref(),
+            Stmt::Fn(decl) => decl.doc.as_deref(),
+            Stmt::Expr(_) | Stmt::Assign(_) => None,
+        }
+    }
+}
+
+impl Documented for Field {
+    fn doc(&self) -> Option<&str> {
+        self.doc.as_deref()
+    }
+}
+
+impl Documented for Variant {
+    fn doc(&self) -> Option<&str> {
+        self.doc.as_deref()
+    }
 }
 
 #[derive(Debug)]
 pub enum Stmt {
     Expr(Expr),
-    Decl(Ident, Expr),
+    Decl(Decl),
+    Assign(Assign),
+    Fn(FnDecl),
+}
+
+#[derive(Debug)]
+pub struct Decl {
+    pub doc: Option<String>,
+    pub mutable: bool,
+    pub name: Ident,
+    pub typ: Option<TypeExpr>,
+    pub expr: Expr,
+}
+
+// `fn name(args) -> ret { body }`. Without `->` the function returns `()`.
+// A `local` function can only be used in its own module or shard.
+#[derive(Debug)]
+pub struct FnDecl {
+    pub doc: Option<String>,
+    pub local: bool,
+    pub name: Ident,
+    pub args: Vec<Arg>,
+    pub ret: Option<TypeExpr>,
+    pub body: Expr,
+    pub span: Span,
+    // The function's type. Filled in by the type checker.
+    pub typ: Option<Type>,
+}
+
+// `extern fn name(args) -> ret`, a function provided by the host.
+#[derive(Debug)]
+pub struct ExternFn {
+    pub doc: Option<String>,
+    pub name: Ident,
+    pub args: Vec<Arg>,
+    pub ret: Option<TypeExpr>,
+    pub span: Span,
+    // The function's type. Filled in by the type checker.
+    pub typ: Option<Type>,
+}
+
+// `const NAME: Type = expr`, evaluated at compile time.
+#[derive(Debug)]
+pub struct ConstDecl {
+    pub doc: Option<String>,
+    pub local: bool,
+    pub name: Ident,
+    pub typ: TypeExpr,
+    pub expr: Expr,
+    pub span: Span,
+    // Filled in by constant evaluation.
+    pub value: Option<Value>,
+}
+
+// `struct Name { field: Type, ... }`
+#[derive(Debug)]
+pub struct StructDecl {
+    pub doc: Option<String>,
+    pub local: bool,
+    pub name: Ident,
+    pub fields: Vec<Field>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct Field {
+    pub doc: Option<String>,
+    pub name: Ident,
+    pub typ: TypeExpr,
+    pub span: Span,
+}
+
+// `enum Name { Variant, ... }`
+#[derive(Debug)]
+pub struct EnumDecl {
+    pub doc: Option<String>,
+    pub name: Ident,
+    pub variants: Vec<Variant>,
+    pub span: Span,
+}
+
+// `impl Name { fn ... }`, the associated functions of a struct or enum.
+// Those whose first argument is `self` are methods, called as `value.name()`.
+#[derive(Debug)]
+pub struct ImplDecl {
+    pub doc: Option<String>,
+    pub name: Ident,
+    pub fns: Vec<FnDecl>,
+    pub span: Span,
+}
+
+impl ImplDecl {
+    // The name a function of the block is called by, like `Point::new`.
+    pub fn path(&self, decl: &FnDecl) -> Ident {
+        format!("{}::{}", self.name, decl.name)
+    }
 }
 
+// `use a::b` brings the module `a::b` into scope as `b`, and `use a::{x, y}`
+// the items `x` and `y` of the module `a`.
 #[derive(Debug)]
+pub struct UseDecl {
+    pub doc: Option<String>,
+    pub path: Vec<Ident>,
+    pub names: Option<Vec<(Ident, Span)>>,
+    pub span: Span,
+}
+
+impl UseDecl {
+    // The path of the module the declaration uses.
+    pub fn module(&self) -> Ident {
+        self.path.join("::")
+    }
+}
+
+// `shard name { items }`, a unit of compilation. It is linked as a module
+// of its own, used by the module that declares it.
+#[derive(Debug)]
+pub struct ShardDecl {
+    pub doc: Option<String>,
+    pub name: Ident,
+    pub items: Vec<Item>,
+    pub span: Span,
+}
+
+// `type Name = Type`, or `type Name<T, ...> = Type` for a generic alias.
+#[derive(Debug)]
+pub struct TypeAlias {
+    pub doc: Option<String>,
+    pub name: Ident,
+    pub params: Vec<Ident>,
+    pub typ: TypeExpr,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct Variant {
+    pub doc: Option<String>,
+    pub name: Ident,
+    pub fields: VariantFields,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub enum VariantFields {
+    // `Variant`
+    Unit,
+    // `Variant(Type, ...)`
+    Tuple(Vec<TypeExpr>),
+    // `Variant { field: Type, ... }`
+    Struct(Vec<Field>),
+}
+
+// `target = expr`, or `target op= expr` for a compound assignment. The
+// target is a variable or a field of one, like `p.pos.x`.
+#[derive(Debug)]
+pub struct Assign {
+    pub target: Expr,
+    pub op: Option<BinOp>,
+    pub expr: Expr,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Copy)]
 pub enum BinOp {
     Add,
     Minus,
     Times,
     Divide,
+    Rem,
+    And,
+    Or,
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    Shr,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl fmt::Display for BinOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let symbol = match self {
+            BinOp::Add => "+",
+            BinOp::Minus => "-",
+            BinOp::Times => "*",
+            BinOp::Divide => "/",
+            BinOp::Rem => "%",
+            BinOp::And => "&&",
+            BinOp::Or => "||",
+            BinOp::BitAnd => "&",
+            BinOp::BitOr => "|",
+            BinOp::BitXor => "^",
+            BinOp::Shl => "<<",
+            BinOp::Shr => ">>",
+            BinOp::Eq => "==",
+            BinOp::Ne => "!=",
+            BinOp::Lt => "<",
+            BinOp::Le => "<=",
+            BinOp::Gt => ">",
+            BinOp::Ge => ">=",
+        };
+        f.write_str(symbol)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum UnOp {
+    Neg,
+    Not,
+}
+
+impl fmt::Display for UnOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let symbol = match self {
+            UnOp::Neg => "-",
+            UnOp::Not => "!",
+        };
+        f.write_str(symbol)
+    }
 }
 
 #[derive(Debug)]
-pub enum Expr {
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+    // Filled in by the type checker.
+    pub typ: Option<Type>,
+}
+
+impl Expr {
+    pub fn new(kind: ExprKind, span: Span) -> Expr {
+        Expr {
+            kind,
+            span,
+            typ: None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ExprKind {
     Block(Vec<Stmt>),
     BinOp(BinOp, Box<Expr>, Box<Expr>),
+    UnOp(UnOp, Box<Expr>),
+    // The else branch is a block or another `if`.
+    If(Box<Expr>, Box<Expr>, Option<Box<Expr>>),
+    While(Option<Label>, Box<Expr>, Box<Expr>),
+    Loop(Option<Label>, Box<Expr>),
+    // Loops over the integer range `start..end`: label, variable, start, end
+    // and body.
+    For(Option<Label>, Ident, Box<Expr>, Box<Expr>, Box<Expr>),
+    Break(Option<Label>, Option<Box<Expr>>),
+    Continue(Option<Label>),
+    Return(Option<Box<Expr>>),
     Literal(Literal),
-    Fn(Vec<Arg>, Box<Expr>),
+    // Arguments, declared return type and body.
+    Fn(Vec<Arg>, Option<TypeExpr>, Box<Expr>),
+    Call(Box<Expr>, Vec<Expr>),
+    // `Name { field: expr, ... }` or `Enum::Variant { field: expr, ... }`,
+    // with the fields in source order.
+    Struct(Ident, Option<Ident>, Vec<(Ident, Expr)>),
+    // `(expr, ...)` with at least one comma.
+    Tuple(Vec<Expr>),
+    // `base.field`, or `base.0` for an element of a tuple.
+    Field(Box<Expr>, Ident),
+    Match(Box<Expr>, Vec<Arm>),
+    Var(Ident),
+    // `Type::name`, like the variant `Shape::Circle`.
+    Path(Ident, Ident),
+    // Placeholder for an expression that failed to parse.
+    Error,
+}
+
+// `pattern => body`, or `pattern if guard => body`.
+#[derive(Debug)]
+pub struct Arm {
+    pub pattern: Pattern,
+    pub guard: Option<Expr>,
+    pub body: Expr,
+}
+
+#[derive(Debug)]
+pub struct Pattern {
+    pub kind: PatternKind,
+    pub span: Span,
+}
+
+#[derive(Debug)]
+pub enum PatternKind {
+    // `_`
+    Wildcard,
+    // A name bound to the matched value.
+    Binding(Ident),
+    // An integer or `bool` literal, possibly negated.
+    Literal(Box<Expr>),
+    // `Enum::Variant`, a unit variant.
+    Path(Ident, Ident),
+    // `Enum::Variant(pattern, ...)`
+    Tuple(Ident, Ident, Vec<Pattern>),
+    // `Name { field: pattern, ... }` or `Enum::Variant { ... }`. Fields
+    // written alone bind their value, and the fields left out must be
+    // allowed by a trailing `..`.
+    Struct(Ident, Option<Ident>, Vec<(Ident, Pattern)>, bool),
 }
 
 #[derive(Debug)]
 pub enum Literal {
-    Int(u64),
-    Float(f64),
-    //String(String),
+    Int(u64, Option<IntTy>),
+    Float(f64, Option<FloatTy>),
+    String(String),
     Bool(bool),
 }
 
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum IntTy {
+    I8,
+    I16,
+    I32,
+    I64,
+    U8,
+    U16,
+    U32,
+    U64,
+}
+
+impl IntTy {
+    pub fn is_signed(&self) -> bool {
+        matches!(self, IntTy::I8 | IntTy::I16 | IntTy::I32 | IntTy::I64)
+    }
+
+    // The largest value of the type.
+    pub fn max(&self) -> u64 {
+        match self {
+            IntTy::I8 => i8::MAX as u64,
+            IntTy::I16 => i16::MAX as u64,
+            IntTy::I32 => i32::MAX as u64,
+            IntTy::I64 => i64::MAX as u64,
+            IntTy::U8 => u8::MAX as u64,
+            IntTy::U16 => u16::MAX as u64,
+            IntTy::U32 => u32::MAX as u64,
+            IntTy::U64 => u64::MAX,
+        }
+    }
+
+    pub fn bits(&self) -> u32 {
+        match self {
+            IntTy::I8 | IntTy::U8 => 8,
+            IntTy::I16 | IntTy::U16 => 16,
+            IntTy::I32 | IntTy::U32 => 32,
+            IntTy::I64 | IntTy::U64 => 64,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum FloatTy {
+    F32,
+    F64,
+}
+
 #[derive(Debug)]
 pub struct Arg {
     pub name: Ident,
-    pub typename: Ident,
+    pub typ: TypeExpr,
+}
+
+// A type as written in the source, resolved by the type checker.
+#[derive(Debug, Clone)]
+pub struct TypeExpr {
+    pub kind: TypeExprKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub enum TypeExprKind {
+    // A named type, like `int`, or `Pair<int>` with generic arguments.
+    Name(Ident, Vec<TypeExpr>),
+    // `()`
+    Unit,
+    // `(int, float)`, or `(int,)` with a single element.
+    Tuple(Vec<TypeExpr>),
+    // `fn(int, int) -> int`. Without `->` the function returns `()`.
+    Fn(Vec<TypeExpr>, Option<Box<TypeExpr>>),
 }
@@ -0,0 +1,639 @@
+use super::ast::*;
+use super::token::Span;
+use super::type_error::TypeError;
+use super::types::Type;
+use std::collections::HashMap;
+
+// Calls nested deeper than this are assumed to recurse forever. Each level
+// takes a few kilobytes of stack in a debug build, so this stays well within
+// the 2 MiB of a thread that is not the main one.
+const MAX_CALL_DEPTH: usize = 64;
+
+// The value of a constant, computed at compile time.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    // Integers narrower than 64 bits are kept sign or zero extended, the same
+    // way they are held in wasm registers.
+    Int(i64, IntTy),
+    Float(f64, FloatTy),
+    Bool(bool),
+    String(String),
+    Unit,
+}
+
+#[derive(Clone)]
+enum Entry<'a> {
+    Value(Value),
+    Fn(&'a FnDecl),
+}
+
+enum State {
+    Evaluating,
+    Done(Value),
+    Failed,
+}
+
+// Why evaluation of an expression stopped without a value.
+enum Flow {
+    Return(Value),
+    // The error has already been reported.
+    Error,
+}
+
+type EvalResult = Result<Value, Flow>;
+
+type Scope<'a> = HashMap<&'a str, Entry<'a>>;
+
+// Evaluates the initializer of every constant in the type checked `program`
+// and stores its value in the declaration. Returns the errors found on the way.
+pub fn eval_consts(program: &mut Program) -> Vec<TypeError> {
+    let mut values = HashMap::new();
+    let mut evaluator = Evaluator::new(program);
+    for item in &program.items {
+        if let Item::Const(decl) = item {
+            if let Ok(value) = evaluator.eval_const(&decl.name, &decl.span) {
+                values.insert(decl.name.clone(), value);
+            }
+        }
+    }
+    let errors = evaluator.errors;
+    for item in program.items.iter_mut() {
+        if let Item::Const(decl) = item {
+            decl.value = values.remove(&decl.name);
+        }
+    }
+    errors
+}
+
+struct Evaluator<'a> {
+    consts: HashMap<&'a str, &'a Expr>,
+    // Function items by the name they are called by.
+    fns: HashMap<Ident, &'a FnDecl>,
+    states: HashMap<&'a str, State>,
+    // Locals and function items of the function being evaluated.
+    scopes: Vec<Scope<'a>>,
+    depth: usize,
+    errors: Vec<TypeError>,
+}
+
+impl<'a> Evaluator<'a> {
+    fn new(program: &'a Program) -> Evaluator<'a> {
+        let mut consts = HashMap::new();
+        let mut fns = HashMap::new();
+        for item in &program.items {
+            match item {
+                Item::Const(decl) => {
+                    consts.insert(decl.name.as_str(), &decl.expr);
+                }
+                Item::Fn(decl) => {
+                    fns.insert(decl.name.clone(), decl);
+                }
+                Item::Impl(block) => {
+                    for decl in &block.fns {
+                        fns.insert(block.path(decl), decl);
+                    }
+                }
+                Item::Extern(_)
+                | Item::Struct(_)
+                | Item::Enum(_)
+                | Item::Alias(_)
+                | Item::Use(_)
+                | Item::Shard(_) => (),
+            }
+        }
+        Evaluator {
+            consts,
+            fns,
+            states: HashMap::new(),
+            scopes: Vec::new(),
+            depth: 0,
+            errors: Vec::new(),
+        }
+    }
+
+    fn error(&mut self, span: &Span, message: String) -> Flow {
+        self.errors.push(TypeError::new(span.clone(), message));
+        Flow::Error
+    }
+
+    fn eval_const(&mut self, name: &'a str, span: &Span) -> EvalResult {
+        match self.states.get(name) {
+            Some(State::Done(value)) => return Ok(value.clone()),
+            Some(State::Failed) => return Err(Flow::Error),
+            Some(State::Evaluating) => {
+                let message = format!("cycle detected when evaluating constant `{}`", name);
+                self.states.insert(name, State::Failed);
+                return Err(self.error(span, message));
+            }
+            None => (),
+        }
+        self.states.insert(name, State::Evaluating);
+        // An initializer sees none of the locals of whoever refers to it.
+        let scopes = std::mem::take(&mut self.scopes);
+        let result = match self.eval_expr(self.consts[name]) {
+            Ok(value) | Err(Flow::Return(value)) => Ok(value),
+            Err(Flow::Error) => Err(Flow::Error),
+        };
+        self.scopes = scopes;
+        let state = match &result {
+            Ok(value) => State::Done(value.clone()),
+            Err(_) => State::Failed,
+        };
+        self.states.insert(name, state);
+        result
+    }
+
+    fn lookup(&self, name: &str) -> Option<&Entry<'a>> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    // Every level of nesting takes a frame of this function, so the larger
+    // cases are left to methods of their own to keep it small.
+    fn eval_expr(&mut self, expr: &'a Expr) -> EvalResult {
+        let value = match &expr.kind {
+            ExprKind::Literal(lit) => literal_value(lit),
+            ExprKind::Block(stmts) => {
+                self.scopes.push(HashMap::new());
+                let result = self.eval_stmts(stmts);
+                self.scopes.pop();
+                result?
+            }
+            ExprKind::BinOp(op, lexpr, rexpr) => self.eval_binop(*op, lexpr, rexpr, &expr.span)?,
+            ExprKind::UnOp(op, operand) => {
+                let value = self.eval_expr(operand)?;
+                self.unop(*op, operand, value, &expr.span)?
+            }
+            ExprKind::If(cond, then, els) => self.eval_if(cond, then, els.as_deref())?,
+            ExprKind::Return(value) => {
+                let value = match value {
+                    Some(value) => self.eval_expr(value)?,
+                    None => Value::Unit,
+                };
+                return Err(Flow::Return(value));
+            }
+            ExprKind::Call(callee, args) => self.eval_call(callee, args, &expr.span)?,
+            ExprKind::Var(name) => self.eval_var(name, &expr.span)?,
+            _ => return Err(self.not_constant(&expr.span)),
+        };
+        // Values of expressions used as statements are discarded.
+        Ok(match expr.typ {
+            Some(Type::Unit) => Value::Unit,
+            _ => value,
+        })
+    }
+
+    // Evaluates the right operand of `&&` and `||` only if it is needed.
+    fn eval_binop(
+        &mut self,
+        op: BinOp,
+        lexpr: &'a Expr,
+        rexpr: &'a Expr,
+        span: &Span,
+    ) -> EvalResult {
+        let lval = self.eval_expr(lexpr)?;
+        match (op, lval) {
+            (BinOp::And, Value::Bool(false)) => Ok(Value::Bool(false)),
+            (BinOp::Or, Value::Bool(true)) => Ok(Value::Bool(true)),
+            (BinOp::And | BinOp::Or, _) => self.eval_expr(rexpr),
+            (op, lval) => {
+                let rval = self.eval_expr(rexpr)?;
+                self.binop(op, lval, rval, span)
+            }
+        }
+    }
+
+    fn eval_if(&mut self, cond: &'a Expr, then: &'a Expr, els: Option<&'a Expr>) -> EvalResult {
+        match (self.eval_expr(cond)?, els) {
+            (Value::Bool(true), _) => self.eval_expr(then),
+            (Value::Bool(false), Some(els)) => self.eval_expr(els),
+            (Value::Bool(false), None) => Ok(Value::Unit),
+            (value, _) => unreachable!("condition {:?} after type checking", value),
+        }
+    }
+
+    fn eval_var(&mut self, name: &'a str, span: &Span) -> EvalResult {
+        match self.lookup(name) {
+            Some(Entry::Value(value)) => Ok(value.clone()),
+            Some(Entry::Fn(_)) => Err(self.not_constant(span)),
+            None if self.consts.contains_key(name) => self.eval_const(name, span),
+            None => Err(self.not_constant(span)),
+        }
+    }
+
+    fn not_constant(&mut self, span: &Span) -> Flow {
+        let message = String::from("this expression cannot be evaluated at compile time");
+        self.error(span, message)
+    }
+
+    fn eval_stmts(&mut self, stmts: &'a [Stmt]) -> EvalResult {
+        // Function items can be called before they are defined.
+        for stmt in stmts {
+            if let Stmt::Fn(decl) = stmt {
+                self.bind(&decl.name, Entry::Fn(decl));
+            }
+        }
+        let mut value = Value::Unit;
+        for stmt in stmts {
+            value = match stmt {
+                Stmt::Expr(expr) => self.eval_expr(expr)?,
+                Stmt::Decl(decl) => {
+                    let value = self.eval_expr(&decl.expr)?;
+                    self.bind(&decl.name, Entry::Value(value));
+                    Value::Unit
+                }
+                Stmt::Assign(assign) => {
+                    let name = match &assign.target.kind {
+                        ExprKind::Var(name) => name,
+                        _ => return Err(self.not_constant(&assign.target.span)),
+                    };
+                    let mut value = self.eval_expr(&assign.expr)?;
+                    if let Some(op) = assign.op {
+                        let current = match self.lookup(name) {
+                            Some(Entry::Value(current)) => current.clone(),
+                            _ => unreachable!("assignment to a non-value after type checking"),
+                        };
+                        value = self.binop(op, current, value, &assign.span)?;
+                    }
+                    let scope = self
+                        .scopes
+                        .iter_mut()
+                        .rev()
+                        .find(|scope| scope.contains_key(name.as_str()))
+                        .expect("unresolved name after type checking");
+                    scope.insert(name.as_str(), Entry::Value(value));
+                    Value::Unit
+                }
+                Stmt::Fn(_) => Value::Unit,
+            };
+        }
+        Ok(value)
+    }
+
+    fn bind(&mut self, name: &'a str, entry: Entry<'a>) {
+        self.scopes.last_mut().unwrap().insert(name, entry);
+    }
+
+    fn eval_call(&mut self, callee: &'a Expr, args: &'a [Expr], span: &Span) -> EvalResult {
+        let decl = match self.callee_fn(callee) {
+            Some(decl) => decl,
+            None => return Err(self.not_constant(&callee.span)),
+        };
+        let mut values = Vec::new();
+        for arg in args {
+            values.push(self.eval_expr(arg)?);
+        }
+        if self.depth == MAX_CALL_DEPTH {
+            let message = String::from("constant evaluation exceeded the call depth limit");
+            return Err(self.error(span, message));
+        }
+        let scopes = self.enter_fn(decl, values);
+        self.depth += 1;
+        let result = self.eval_expr(&decl.body);
+        self.depth -= 1;
+        self.scopes = scopes;
+        match result {
+            Ok(value) | Err(Flow::Return(value)) => Ok(value),
+            Err(Flow::Error) => Err(Flow::Error),
+        }
+    }
+
+    // The function item `callee` names, if it names one.
+    fn callee_fn(&self, callee: &'a Expr) -> Option<&'a FnDecl> {
+        match &callee.kind {
+            ExprKind::Var(name) => match self.lookup(name) {
+                Some(Entry::Fn(decl)) => Some(*decl),
+                Some(Entry::Value(_)) => None,
+                None => self.fns.get(name.as_str()).copied(),
+            },
+            _ => None,
+        }
+    }
+
+    // Switches to the scopes of a call to `decl` with the arguments `values`,
+    // and returns those of the caller.
+    fn enter_fn(&mut self, decl: &'a FnDecl, values: Vec<Value>) -> Vec<Scope<'a>> {
+        // Functions see the function items of their enclosing scopes, but
+        // none of the locals.
+        let fns_only = self
+            .scopes
+            .iter()
+            .map(|scope| {
+                scope
+                    .iter()
+                    .filter(|(_, entry)| matches!(entry, Entry::Fn(_)))
+                    .map(|(name, entry)| (*name, entry.clone()))
+                    .collect()
+            })
+            .collect();
+        let scopes = std::mem::replace(&mut self.scopes, fns_only);
+        let mut arg_scope = HashMap::new();
+        for (arg, value) in decl.args.iter().zip(values) {
+            arg_scope.insert(arg.name.as_str(), Entry::Value(value));
+        }
+        self.scopes.push(arg_scope);
+        scopes
+    }
+
+    fn unop(&mut self, op: UnOp, operand: &Expr, value: Value, span: &Span) -> EvalResult {
+        let value = match (op, value) {
+            // `-128i8` is in range although `128i8` is not: the literal wraps
+            // around to -128, which negates to itself.
+            (UnOp::Neg, Value::Int(a, ty))
+                if matches!(operand.kind, ExprKind::Literal(Literal::Int(..))) =>
+            {
+                Value::Int(wrap(a.wrapping_neg(), ty), ty)
+            }
+            (UnOp::Neg, Value::Int(a, ty)) => {
+                match widen(a, ty).checked_neg().and_then(|val| narrow(val, ty)) {
+                    Some(val) => Value::Int(val, ty),
+                    None => {
+                        let message = String::from("attempt to negate with overflow");
+                        return Err(self.error(span, message));
+                    }
+                }
+            }
+            (UnOp::Neg, Value::Float(a, ty)) => Value::Float(-a, ty),
+            (UnOp::Not, Value::Int(a, ty)) => Value::Int(wrap(!a, ty), ty),
+            (UnOp::Not, Value::Bool(b)) => Value::Bool(!b),
+            (op, value) => unreachable!("unary `{}` on {:?} after type checking", op, value),
+        };
+        Ok(value)
+    }
+
+    fn binop(&mut self, op: BinOp, lval: Value, rval: Value, span: &Span) -> EvalResult {
+        let value = match (lval, rval) {
+            (Value::Int(a, ty), Value::Int(b, _)) => {
+                let signed = ty.is_signed();
+                let int = |val: i64| Value::Int(wrap(val, ty), ty);
+                let cmp = if signed {
+                    a.cmp(&b)
+                } else {
+                    (a as u64).cmp(&(b as u64))
+                };
+                match op {
+                    BinOp::Add | BinOp::Minus | BinOp::Times | BinOp::Divide | BinOp::Rem => {
+                        if matches!(op, BinOp::Divide | BinOp::Rem) && b == 0 {
+                            let message = String::from("attempt to divide by zero");
+                            return Err(self.error(span, message));
+                        }
+                        // Computed exactly, so a result that does not fit the
+                        // type is an overflow, even for the narrow types.
+                        let (x, y) = (widen(a, ty), widen(b, ty));
+                        let (val, verb) = match op {
+                            BinOp::Add => (x.checked_add(y), "add"),
+                            BinOp::Minus => (x.checked_sub(y), "subtract"),
+                            BinOp::Times => (x.checked_mul(y), "multiply"),
+                            BinOp::Divide => (x.checked_div(y), "divide"),
+                            _ => (x.checked_rem(y), "calculate the remainder"),
+                        };
+                        match val.and_then(|val| narrow(val, ty)) {
+                            Some(val) => Value::Int(val, ty),
+                            None => {
+                                let message = format!("attempt to {} with overflow", verb);
+                                return Err(self.error(span, message));
+                            }
+                        }
+                    }
+                    BinOp::BitAnd => int(a & b),
+                    BinOp::BitOr => int(a | b),
+                    BinOp::BitXor => int(a ^ b),
+                    BinOp::Shl | BinOp::Shr => {
+                        // Negative counts are out of range as well.
+                        if b as u64 >= ty.bits() as u64 {
+                            let dir = match op {
+                                BinOp::Shl => "left",
+                                _ => "right",
+                            };
+                            let message = format!("attempt to shift {} with overflow", dir);
+                            return Err(self.error(span, message));
+                        }
+                        match op {
+                            BinOp::Shl => int(a << b),
+                            _ if signed => int(a >> b),
+                            _ => int(((a as u64) >> b) as i64),
+                        }
+                    }
+                    BinOp::Eq => Value::Bool(cmp.is_eq()),
+                    BinOp::Ne => Value::Bool(cmp.is_ne()),
+                    BinOp::Lt => Value::Bool(cmp.is_lt()),
+                    BinOp::Le => Value::Bool(cmp.is_le()),
+                    BinOp::Gt => Value::Bool(cmp.is_gt()),
+                    BinOp::Ge => Value::Bool(cmp.is_ge()),
+                    BinOp::And | BinOp::Or => {
+                        unreachable!("`{}` on integers after type checking", op)
+                    }
+                }
+            }
+            (Value::Float(a, ty), Value::Float(b, _)) => {
+                let float = |val: f64| Value::Float(round(val, ty), ty);
+                match op {
+                    BinOp::Add => float(a + b),
+                    BinOp::Minus => float(a - b),
+                    BinOp::Times => float(a * b),
+                    BinOp::Divide => float(a / b),
+                    BinOp::Eq => Value::Bool(a == b),
+                    BinOp::Ne => Value::Bool(a != b),
+                    BinOp::Lt => Value::Bool(a < b),
+                    BinOp::Le => Value::Bool(a <= b),
+                    BinOp::Gt => Value::Bool(a > b),
+                    BinOp::Ge => Value::Bool(a >= b),
+                    _ => unreachable!("`{}` on floats after type checking", op),
+                }
+            }
+            (Value::Bool(a), Value::Bool(b)) => match op {
+                BinOp::Eq => Value::Bool(a == b),
+                BinOp::Ne | BinOp::BitXor => Value::Bool(a != b),
+                BinOp::BitAnd => Value::Bool(a & b),
+                BinOp::BitOr => Value::Bool(a | b),
+                _ => unreachable!("`{}` on bools after type checking", op),
+            },
+            (lval, rval) => {
+                unreachable!("`{}` on {:?} and {:?} after type checking", op, lval, rval)
+            }
+        };
+        Ok(value)
+    }
+}
+
+fn literal_value(lit: &Literal) -> Value {
+    match lit {
+        Literal::Int(u, ty) => {
+            let ty = ty.unwrap_or(IntTy::I64);
+            Value::Int(wrap(*u as i64, ty), ty)
+        }
+        Literal::Float(z, ty) => {
+            let ty = ty.unwrap_or(FloatTy::F64);
+            Value::Float(round(*z, ty), ty)
+        }
+        Literal::String(s) => Value::String(s.clone()),
+        Literal::Bool(b) => Value::Bool(*b),
+    }
+}
+
+// Truncates `val` to the width of `ty` and extends it back to 64 bits.
+fn wrap(val: i64, ty: IntTy) -> i64 {
+    match ty {
+        IntTy::I8 => val as i8 as i64,
+        IntTy::I16 => val as i16 as i64,
+        IntTy::I32 => val as i32 as i64,
+        IntTy::U8 => val as u8 as i64,
+        IntTy::U16 => val as u16 as i64,
+        IntTy::U32 => val as u32 as i64,
+        IntTy::I64 | IntTy::U64 => val,
+    }
+}
+
+// The value of an integer of type `ty`, exactly.
+fn widen(val: i64, ty: IntTy) -> i128 {
+    if ty.is_signed() {
+        val as i128
+    } else {
+        val as u64 as i128
+    }
+}
+
+// Converts an exact result back to the representation of `ty`, or `None` if
+// it does not fit.
+fn narrow(val: i128, ty: IntTy) -> Option<i64> {
+    let min = if ty.is_signed() {
+        -(ty.max() as i128) - 1
+    } else {
+        0
+    };
+    if val < min || val > ty.max() as i128 {
+        None
+    } else {
+        Some(val as i64)
+    }
+}
+
+fn round(val: f64, ty: FloatTy) -> f64 {
+    match ty {
+        FloatTy::F32 => val as f32 as f64,
+        FloatTy::F64 => val,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::token_kind::TokenKind;
+    use crate::typeck;
+    use logos::Logos;
+
+    // Type checks `src` as a program of one module, which evaluates its
+    // constants, and returns the program with the error messages.
+    fn eval(src: &str) -> (Program, Vec<String>) {
+        let src = format!("{}fn main() -> int {{ 0 }}\n", src);
+        let (items, parse_errors) = Parser::new(TokenKind::lexer(&src)).parse_items();
+        assert!(parse_errors.is_empty(), "{:?}", parse_errors);
+        let root = Module {
+            path: Ident::new(),
+            items: 0..items.len(),
+            shard: None,
+            imports: Vec::new(),
+            consts: Vec::new(),
+            externs: Vec::new(),
+        };
+        let mut program = Program {
+            items,
+            modules: vec![root],
+        };
+        let errors = typeck::check(&mut program)
+            .into_iter()
+            .filter(|err| !err.is_warning)
+            .map(|err| err.message)
+            .collect();
+        (program, errors)
+    }
+
+    fn value<'a>(program: &'a Program, name: &str) -> Option<&'a Value> {
+        program.items.iter().find_map(|item| match item {
+            Item::Const(decl) if decl.name == name => decl.value.as_ref(),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn cycles() {
+        let (program, errors) = eval("const A: int = B\nconst B: int = A\nconst C: int = C\n");
+        assert_eq!(
+            errors,
+            [
+                "cycle detected when evaluating constant `A`",
+                "cycle detected when evaluating constant `C`",
+            ]
+        );
+        assert_eq!(value(&program, "A"), None);
+        assert_eq!(value(&program, "B"), None);
+    }
+
+    #[test]
+    fn call_depth_limit() {
+        let (_, errors) = eval("fn f(n: int) -> int { f(n + 1) }\nconst X: int = f(0)\n");
+        assert_eq!(
+            errors,
+            ["constant evaluation exceeded the call depth limit"]
+        );
+        let (_, errors) = eval(
+            "fn g(n: int) -> int {\n    if n >= 0 {\n        let m = n + 1\n        { 1 + g(m) }\n    } else {\n        0\n    }\n}\nconst Y: int = g(0)\n",
+        );
+        assert_eq!(
+            errors,
+            ["constant evaluation exceeded the call depth limit"]
+        );
+    }
+
+    #[test]
+    fn divide_by_zero() {
+        let (program, errors) =
+            eval("const A: int = 1 / 0\nconst B: u8 = 1u8 % 0u8\nconst C: int = 7 / 2\n");
+        assert_eq!(
+            errors,
+            ["attempt to divide by zero", "attempt to divide by zero"]
+        );
+        assert_eq!(value(&program, "C"), Some(&Value::Int(3, IntTy::I64)));
+    }
+
+    #[test]
+    fn return_in_called_fn() {
+        let (program, errors) = eval(
+            "fn abs(x: int) -> int {\n    if x < 0 { return -x }\n    x\n}\nconst A: int = abs(-5)\nconst B: int = abs(3)\n",
+        );
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(value(&program, "A"), Some(&Value::Int(5, IntTy::I64)));
+        assert_eq!(value(&program, "B"), Some(&Value::Int(3, IntTy::I64)));
+    }
+
+    #[test]
+    fn narrow_ints() {
+        let (program, errors) = eval(
+            "const A: u8 = 3u8 << 7\nconst B: u8 = !0u8\nconst C: i8 = -128i8\nconst D: i16 = -(-32767i16)\n",
+        );
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(value(&program, "A"), Some(&Value::Int(128, IntTy::U8)));
+        assert_eq!(value(&program, "B"), Some(&Value::Int(255, IntTy::U8)));
+        assert_eq!(value(&program, "C"), Some(&Value::Int(-128, IntTy::I8)));
+        assert_eq!(value(&program, "D"), Some(&Value::Int(32767, IntTy::I16)));
+    }
+
+    #[test]
+    fn overflow() {
+        let (_, errors) = eval(
+            "const A: int = 9223372036854775807 + 1\nconst B: u8 = 255u8 + 1u8\nconst C: u32 = 0u32 - 1u32\nconst D: i8 = -(-128i8)\nconst E: i8 = -128i8 / -1i8\nconst F: int = 1 << 70\nconst G: u8 = 1u8 >> 8u8\n",
+        );
+        assert_eq!(
+            errors,
+            [
+                "attempt to add with overflow",
+                "attempt to add with overflow",
+                "attempt to subtract with overflow",
+                "attempt to negate with overflow",
+                "attempt to divide with overflow",
+                "attempt to shift left with overflow",
+                "attempt to shift right with overflow",
+            ]
+        );
+    }
+}
@@ -0,0 +1,356 @@
+use super::types::{Type, VariantKind};
+use std::slice;
+
+// Exhaustiveness and reachability of `match` arms are both decided by
+// usefulness: a pattern is useful after some rows if it matches a value none
+// of them match. A `match` is exhaustive if the wildcard is not useful after
+// its unguarded arms.
+
+// A pattern reduced to what matters for matching.
+#[derive(Debug, Clone)]
+pub enum Pat {
+    // Matches anything, like `_` or a binding.
+    Wild,
+    Ctor(Ctor, Vec<Pat>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Ctor {
+    // The only constructor of a struct, with one argument per field.
+    Single,
+    // An enum variant by index, with one argument per field.
+    Variant(usize),
+    Bool(bool),
+    Int(i128),
+}
+
+// Whether `pat` matches a value of type `typ` that none of `rows` match.
+pub fn is_useful(rows: &[Pat], pat: &Pat, typ: &Type) -> bool {
+    let rows: Vec<Vec<Pat>> = rows.iter().map(|row| vec![row.clone()]).collect();
+    useful(&rows, slice::from_ref(pat), slice::from_ref(typ))
+}
+
+// A value of type `typ` that none of `rows` match, written as a pattern, if
+// there is one.
+pub fn missing(rows: &[Pat], typ: &Type) -> Option<String> {
+    let rows: Vec<Vec<Pat>> = rows.iter().map(|row| vec![row.clone()]).collect();
+    let witness = witness(&rows, slice::from_ref(typ))?;
+    Some(show(&witness[0], typ))
+}
+
+// All the constructors of `typ`, if there are finitely many.
+fn signature(typ: &Type) -> Option<Vec<Ctor>> {
+    match typ.unalias() {
+        Type::Struct(_) | Type::Tuple(_) => Some(vec![Ctor::Single]),
+        Type::Enum(en) => Some((0..en.variants.len()).map(Ctor::Variant).collect()),
+        Type::Bool => Some(vec![Ctor::Bool(false), Ctor::Bool(true)]),
+        _ => None,
+    }
+}
+
+// The types of the arguments of `ctor`.
+fn fields(ctor: &Ctor, typ: &Type) -> Vec<Type> {
+    let payload = match (ctor, typ.unalias()) {
+        (Ctor::Single, Type::Struct(st) | Type::Tuple(st)) => &**st,
+        (Ctor::Variant(i), Type::Enum(en)) => &en.variants[*i].payload,
+        _ => return Vec::new(),
+    };
+    payload.fields.iter().map(|(_, typ)| typ.clone()).collect()
+}
+
+// The row with its first column replaced by the arguments of `ctor`, if it
+// can match a value built with `ctor`.
+fn specialize(row: &[Pat], ctor: &Ctor, arity: usize) -> Option<Vec<Pat>> {
+    let mut head = match &row[0] {
+        Pat::Wild => vec![Pat::Wild; arity],
+        Pat::Ctor(c, args) if c == ctor => args.clone(),
+        Pat::Ctor(..) => return None,
+    };
+    head.extend_from_slice(&row[1..]);
+    Some(head)
+}
+
+fn specialize_all(rows: &[Vec<Pat>], ctor: &Ctor, arity: usize) -> Vec<Vec<Pat>> {
+    rows.iter()
+        .filter_map(|row| specialize(row, ctor, arity))
+        .collect()
+}
+
+// The rows whose first column matches anything, without it.
+fn default(rows: &[Vec<Pat>]) -> Vec<Vec<Pat>> {
+    rows.iter()
+        .filter(|row| matches!(row[0], Pat::Wild))
+        .map(|row| row[1..].to_vec())
+        .collect()
+}
+
+fn head_ctors(rows: &[Vec<Pat>]) -> Vec<Ctor> {
+    let mut ctors = Vec::new();
+    for row in rows {
+        if let Pat::Ctor(c, _) = &row[0] {
+            if !ctors.contains(c) {
+                ctors.push(c.clone());
+            }
+        }
+    }
+    ctors
+}
+
+// The constructors of the first column's type, if the rows use every one.
+fn complete_signature(rows: &[Vec<Pat>], typ: &Type) -> Option<Vec<Ctor>> {
+    let used = head_ctors(rows);
+    signature(typ).filter(|all| all.iter().all(|c| used.contains(c)))
+}
+
+fn with_types(ctor_fields: Vec<Type>, rest: &[Type]) -> Vec<Type> {
+    let mut types = ctor_fields;
+    types.extend_from_slice(rest);
+    types
+}
+
+fn useful(rows: &[Vec<Pat>], pats: &[Pat], types: &[Type]) -> bool {
+    if pats.is_empty() {
+        return rows.is_empty();
+    }
+    match &pats[0] {
+        Pat::Ctor(ctor, args) => {
+            let types = with_types(fields(ctor, &types[0]), &types[1..]);
+            let rows = specialize_all(rows, ctor, args.len());
+            let pats = specialize(pats, ctor, args.len()).unwrap();
+            useful(&rows, &pats, &types)
+        }
+        Pat::Wild => match complete_signature(rows, &types[0]) {
+            Some(all) => all.iter().any(|ctor| {
+                let ctor_fields = fields(ctor, &types[0]);
+                let arity = ctor_fields.len();
+                let types = with_types(ctor_fields, &types[1..]);
+                let rows = specialize_all(rows, ctor, arity);
+                let pats = specialize(pats, ctor, arity).unwrap();
+                useful(&rows, &pats, &types)
+            }),
+            None => useful(&default(rows), &pats[1..], &types[1..]),
+        },
+    }
+}
+
+// Like `useful` for a row of wildcards, but returns the values matched.
+fn witness(rows: &[Vec<Pat>], types: &[Type]) -> Option<Vec<Pat>> {
+    if types.is_empty() {
+        return rows.is_empty().then(Vec::new);
+    }
+    if let Some(all) = complete_signature(rows, &types[0]) {
+        for ctor in all {
+            let ctor_fields = fields(&ctor, &types[0]);
+            let arity = ctor_fields.len();
+            let types = with_types(ctor_fields, &types[1..]);
+            if let Some(mut found) = witness(&specialize_all(rows, &ctor, arity), &types) {
+                let args = found.drain(..arity).collect();
+                found.insert(0, Pat::Ctor(ctor, args));
+                return Some(found);
+            }
+        }
+        return None;
+    }
+    let mut found = witness(&default(rows), &types[1..])?;
+    // Name a constructor the rows leave out, unless they use none at all.
+    let used = head_ctors(rows);
+    let unused = signature(&types[0])
+        .filter(|_| !used.is_empty())
+        .and_then(|all| all.into_iter().find(|c| !used.contains(c)));
+    let head = match unused {
+        Some(ctor) => {
+            let arity = fields(&ctor, &types[0]).len();
+            Pat::Ctor(ctor, vec![Pat::Wild; arity])
+        }
+        None => Pat::Wild,
+    };
+    found.insert(0, head);
+    Some(found)
+}
+
+// Writes `pat` the way it would appear in the source.
+fn show(pat: &Pat, typ: &Type) -> String {
+    let (ctor, args) = match pat {
+        Pat::Wild => return String::from("_"),
+        Pat::Ctor(ctor, args) => (ctor, args),
+    };
+    let (name, kind, payload) = match (ctor, typ.unalias()) {
+        (Ctor::Bool(b), _) => return b.to_string(),
+        (Ctor::Int(i), _) => return i.to_string(),
+        (Ctor::Single, Type::Struct(st)) => (st.name.clone(), VariantKind::Struct, &**st),
+        (Ctor::Single, Type::Tuple(st)) => (String::new(), VariantKind::Tuple, &**st),
+        (Ctor::Variant(i), Type::Enum(en)) => {
+            let variant = &en.variants[*i];
+            let name = format!("{}::{}", en.name, variant.name);
+            (name, variant.kind, &variant.payload)
+        }
+        _ => unreachable!("constructor of the wrong type"),
+    };
+    let shown = args
+        .iter()
+        .zip(&payload.fields)
+        .map(|(arg, (field, typ))| (field, show(arg, typ)));
+    match kind {
+        VariantKind::Unit => name,
+        VariantKind::Tuple => {
+            let args: Vec<String> = shown.map(|(_, arg)| arg).collect();
+            format!("{}({})", name, args.join(", "))
+        }
+        // Fields that match anything are left to `..`.
+        VariantKind::Struct => {
+            let mut parts: Vec<String> = shown
+                .filter(|(_, arg)| arg != "_")
+                .map(|(field, arg)| format!("{}: {}", field, arg))
+                .collect();
+            if parts.len() < args.len() {
+                parts.push(String::from(".."));
+            }
+            if parts.is_empty() {
+                format!("{} {{}}", name)
+            } else {
+                format!("{} {{ {} }}", name, parts.join(", "))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{Ident, IntTy, Module, Program};
+    use crate::parser::Parser;
+    use crate::token_kind::TokenKind;
+    use crate::typeck;
+    use crate::types::{EnumType, StructType, VariantType};
+    use logos::Logos;
+    use std::rc::Rc;
+
+    fn ctor(ctor: Ctor, args: Vec<Pat>) -> Pat {
+        Pat::Ctor(ctor, args)
+    }
+
+    fn bool(b: bool) -> Pat {
+        ctor(Ctor::Bool(b), Vec::new())
+    }
+
+    fn int(i: i128) -> Pat {
+        ctor(Ctor::Int(i), Vec::new())
+    }
+
+    // `enum Opt { None, Some(bool) }`
+    fn opt() -> Type {
+        let variant = |name: &str, kind, types| VariantType {
+            name: name.to_string(),
+            kind,
+            payload: StructType {
+                name: format!("Opt::{}", name),
+                ..StructType::tuple(types)
+            },
+        };
+        Type::Enum(Rc::new(EnumType {
+            name: String::from("Opt"),
+            variants: vec![
+                variant("None", VariantKind::Unit, Vec::new()),
+                variant("Some", VariantKind::Tuple, vec![Type::Bool]),
+            ],
+        }))
+    }
+
+    fn none() -> Pat {
+        ctor(Ctor::Variant(0), Vec::new())
+    }
+
+    fn some(pat: Pat) -> Pat {
+        ctor(Ctor::Variant(1), vec![pat])
+    }
+
+    #[test]
+    fn bools() {
+        assert_eq!(missing(&[], &Type::Bool).as_deref(), Some("_"));
+        assert_eq!(
+            missing(&[bool(true)], &Type::Bool).as_deref(),
+            Some("false")
+        );
+        assert_eq!(missing(&[bool(true), bool(false)], &Type::Bool), None);
+        assert!(is_useful(&[bool(true)], &bool(false), &Type::Bool));
+        assert!(!is_useful(&[bool(true)], &bool(true), &Type::Bool));
+        assert!(!is_useful(
+            &[bool(false), bool(true)],
+            &Pat::Wild,
+            &Type::Bool
+        ));
+    }
+
+    #[test]
+    fn ints() {
+        let typ = Type::Int(IntTy::I64);
+        assert_eq!(missing(&[int(1), int(2)], &typ).as_deref(), Some("_"));
+        assert_eq!(missing(&[int(1), Pat::Wild], &typ), None);
+        assert!(is_useful(&[int(1)], &int(2), &typ));
+        assert!(!is_useful(&[int(1)], &int(1), &typ));
+        assert!(!is_useful(&[int(1), Pat::Wild], &int(3), &typ));
+        assert!(!is_useful(&[Pat::Wild], &int(-1), &typ));
+    }
+
+    #[test]
+    fn nested_variants() {
+        let typ = opt();
+        let rows = [some(bool(true)), none()];
+        assert_eq!(missing(&rows, &typ).as_deref(), Some("Opt::Some(false)"));
+        assert_eq!(
+            missing(&[some(Pat::Wild)], &typ).as_deref(),
+            Some("Opt::None")
+        );
+        assert_eq!(missing(&[none(), some(Pat::Wild)], &typ), None);
+        assert!(is_useful(&rows, &some(Pat::Wild), &typ));
+        assert!(!is_useful(&[some(Pat::Wild)], &some(bool(false)), &typ));
+        assert!(!is_useful(
+            &[some(bool(true)), some(bool(false))],
+            &some(Pat::Wild),
+            &typ
+        ));
+
+        // `(bool, bool)`
+        let pair = Type::Tuple(Rc::new(StructType::tuple(vec![Type::Bool, Type::Bool])));
+        let rows = [
+            ctor(Ctor::Single, vec![bool(true), Pat::Wild]),
+            ctor(Ctor::Single, vec![Pat::Wild, bool(true)]),
+        ];
+        assert_eq!(missing(&rows, &pair).as_deref(), Some("(false, false)"));
+    }
+
+    #[test]
+    fn empty_enums() {
+        let typ = Type::Enum(Rc::new(EnumType {
+            name: String::from("Void"),
+            variants: Vec::new(),
+        }));
+        assert_eq!(missing(&[], &typ), None);
+        assert!(!is_useful(&[], &Pat::Wild, &typ));
+    }
+
+    // The type checker leaves guarded arms out of the rows.
+    #[test]
+    fn guards() {
+        let src = "fn main() -> int {\n    let c = true\n    match c {\n        true if c => 1\n        true => 2\n        false => 3\n    }\n}\nfn f(c: bool) -> int {\n    match c {\n        true if c => 1\n        false => 2\n    }\n}\n";
+        let (items, parse_errors) = Parser::new(TokenKind::lexer(src)).parse_items();
+        assert!(parse_errors.is_empty(), "{:?}", parse_errors);
+        let root = Module {
+            path: Ident::new(),
+            items: 0..items.len(),
+            shard: None,
+            imports: Vec::new(),
+            consts: Vec::new(),
+            externs: Vec::new(),
+        };
+        let mut program = Program {
+            items,
+            modules: vec![root],
+        };
+        let errors: Vec<_> = typeck::check(&mut program)
+            .into_iter()
+            .map(|err| err.message)
+            .collect();
+        assert_eq!(errors, ["non-exhaustive patterns: `true` not covered"]);
+    }
+}
@@ -1,4 +1,7 @@
 mod ast;
+mod const_eval;
+mod exhaustiveness;
+mod modules;
 mod parse_error;
 mod parser;
 mod precedence;
@@ -6,25 +9,56 @@ mod token;
 mod token_buffer;
 mod token_kind;
 mod translate;
+mod type_error;
+mod typeck;
+mod types;
 mod util;
 mod wasm;
 
-use logos::Logos;
-use parser::Parser;
-use std::{env, fs};
-use token_kind::TokenKind;
+use modules::Sources;
+use std::{env, fmt, fs, process};
+use token::Span;
+use wasm::{WASMBinary, WriteContext};
 
 fn main() {
     let mut dir = env::current_dir().unwrap();
-    dir.push("test.sd");
-    println!("{:?}", dir.as_os_str());
-    let bytes = fs::read(dir).unwrap();
-    let foo = String::from_utf8_lossy(bytes.as_slice());
-    let lex = TokenKind::lexer(&foo);
-    let mut parser = Parser::new(lex);
-    parser.exhaust();
-    println!("{}", parser);
-    let expr = parser.parse_program().unwrap();
+    dir.push(
+        env::args()
+            .nth(1)
+            .unwrap_or_else(|| String::from("test.sd")),
+    );
+    let mut sources = Sources::new();
+    let (mut expr, parse_errors, errors) = modules::load(&dir, &mut sources);
+    for err in &parse_errors {
+        report(&sources, &err.span, err);
+    }
+    for err in &errors {
+        report(&sources, &err.span, err);
+    }
+    if !parse_errors.is_empty() || !errors.is_empty() {
+        process::exit(1);
+    }
 
-    println!("{:?}", &expr);
+    let errors = typeck::check(&mut expr);
+    for err in &errors {
+        report(&sources, &err.span, err);
+    }
+    if errors.iter().any(|err| !err.is_warning) {
+        process::exit(1);
+    }
+
+    // Shards are written next to the main module, named after them.
+    for (shard, module) in translate::translate(&expr) {
+        let binary = WASMBinary::as_bytes(&module).unwrap();
+        let path = match shard {
+            Some(name) => dir.with_file_name(name).with_extension("wasm"),
+            None => dir.with_extension("wasm"),
+        };
+        fs::write(path, binary).unwrap();
+    }
+}
+
+fn report(sources: &Sources, span: &Span, err: &dyn fmt::Display) {
+    let (file, line, col) = sources.locate(span.start);
+    eprintln!("{}:{}:{}: {}", file.display(), line, col, err);
 }
@@ -0,0 +1,308 @@
+use super::ast::*;
+use super::parse_error::ParseError;
+use super::parser::Parser;
+use super::token::Span;
+use super::token_kind::TokenKind;
+use super::type_error::TypeError;
+use logos::Logos;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// The text of every source file of a program, one after the other, so that a
+// span locates both the file and the position in it.
+pub struct Sources {
+    text: String,
+    // Each file with the offset of its text.
+    files: Vec<(PathBuf, usize)>,
+}
+
+impl Sources {
+    pub fn new() -> Sources {
+        Sources {
+            text: String::new(),
+            files: Vec::new(),
+        }
+    }
+
+    // Appends the text of `file`, and returns its offset.
+    fn add(&mut self, file: &Path, text: &str) -> usize {
+        let base = self.text.len();
+        self.text.push_str(text);
+        self.files.push((file.to_path_buf(), base));
+        base
+    }
+
+    // The file of a byte offset, and the 1-based line and column in it.
+    pub fn locate(&self, offset: usize) -> (&Path, usize, usize) {
+        let index = self.files.partition_point(|(_, base)| *base <= offset);
+        let (file, base) = &self.files[index.max(1) - 1];
+        let end = self
+            .files
+            .get(index)
+            .map_or(self.text.len(), |(_, next)| *next);
+        let before = &self.text[*base..offset.min(end)];
+        let line = before.matches('\n').count() + 1;
+        let col = before.chars().rev().take_while(|&c| c != '\n').count() + 1;
+        (file, line, col)
+    }
+}
+
+// Loads the program whose root module is in `file`, along with the modules
+// it uses, and links them into one. The module `a::b` is the file `a/b.sd`
+// under the directory of the root module.
+pub fn load(file: &Path, sources: &mut Sources) -> (Program, Vec<ParseError>, Vec<TypeError>) {
+    let mut loader = Loader {
+        root: file.parent().map(Path::to_path_buf).unwrap_or_default(),
+        sources,
+        loaded: HashSet::new(),
+        stack: Vec::new(),
+        items: Vec::new(),
+        modules: Vec::new(),
+        parse_errors: Vec::new(),
+        errors: Vec::new(),
+    };
+    let text = fs::read(file).unwrap();
+    loader.load_module(Ident::new(), file, &String::from_utf8_lossy(&text));
+    let program = Program {
+        items: loader.items,
+        modules: loader.modules,
+    };
+    (program, loader.parse_errors, loader.errors)
+}
+
+struct Loader<'a> {
+    root: PathBuf,
+    sources: &'a mut Sources,
+    // The paths of the modules and shards loaded or being loaded.
+    loaded: HashSet<Ident>,
+    // The files of the modules being loaded, outermost first.
+    stack: Vec<PathBuf>,
+    // The linked items and modules. A module comes after those it uses.
+    items: Vec<Item>,
+    modules: Vec<Module>,
+    parse_errors: Vec<ParseError>,
+    errors: Vec<TypeError>,
+}
+
+impl Loader<'_> {
+    fn load_module(&mut self, path: Ident, file: &Path, text: &str) {
+        self.loaded.insert(path.clone());
+        // The lexer starts at the file's offset, so spans are offsets into
+        // all of the sources.
+        let base = self.sources.add(file, text);
+        let mut lexer = TokenKind::lexer(&self.sources.text);
+        lexer.bump(base);
+        let (items, errors) = Parser::new(lexer).parse_items();
+        self.parse_errors.extend(errors);
+
+        self.stack.push(file.to_path_buf());
+        self.link_module(path, None, items);
+        self.stack.pop();
+    }
+
+    // Links the items of the module `path`, after the modules they use. A
+    // shard declared among them is linked as a module of its own, and
+    // replaced by a `use` of it.
+    fn link_module(&mut self, path: Ident, shard: Option<Ident>, mut items: Vec<Item>) {
+        for item in items.iter_mut() {
+            match item {
+                Item::Use(decl) => self.load_use(decl),
+                Item::Shard(decl) => {
+                    let mut used = match path.as_str() {
+                        "" => Vec::new(),
+                        path => path.split("::").map(String::from).collect(),
+                    };
+                    used.push(decl.name.clone());
+                    let shard_path = used.join("::");
+                    if !self.loaded.insert(shard_path.clone()) {
+                        let message =
+                            format!("the shard `{}` is defined multiple times", decl.name);
+                        self.error(&decl.span, message);
+                        continue;
+                    }
+                    let shard_items = std::mem::take(&mut decl.items);
+                    self.link_module(shard_path, Some(decl.name.clone()), shard_items);
+                    *item = Item::Use(UseDecl {
+                        doc: decl.doc.take(),
+                        path: used,
+                        names: None,
+                        span: decl.span.clone(),
+                    });
+                }
+                _ => (),
+            }
+        }
+
+        let start = self.items.len();
+        let module = Module {
+            path,
+            items: start..start + items.len(),
+            shard,
+            imports: Vec::new(),
+            consts: Vec::new(),
+            externs: Vec::new(),
+        };
+        for item in items.iter_mut() {
+            let name = match item {
+                Item::Fn(FnDecl { name, .. })
+                | Item::Extern(ExternFn { name, .. })
+                | Item::Const(ConstDecl { name, .. })
+                | Item::Struct(StructDecl { name, .. })
+                | Item::Enum(EnumDecl { name, .. })
+                | Item::Alias(TypeAlias { name, .. }) => name,
+                Item::Impl(_) | Item::Use(_) | Item::Shard(_) => continue,
+            };
+            *name = module.qualify(name);
+        }
+        self.items.extend(items);
+        self.modules.push(module);
+    }
+
+    fn load_use(&mut self, decl: &UseDecl) {
+        let path = decl.module();
+        let mut file = self.root.clone();
+        file.extend(&decl.path);
+        file.set_extension("sd");
+        if self.stack.contains(&file) {
+            let message = format!("cycle detected when loading module `{}`", path);
+            self.error(&decl.span, message);
+            return;
+        }
+        if self.loaded.contains(&path) {
+            return;
+        }
+        match fs::read(&file) {
+            Ok(text) => self.load_module(path, &file, &String::from_utf8_lossy(&text)),
+            Err(_) => {
+                let message = format!(
+                    "cannot find module `{}`, expected the file `{}`",
+                    path,
+                    file.display()
+                );
+                self.error(&decl.span, message);
+            }
+        }
+    }
+
+    fn error(&mut self, span: &Span, message: String) {
+        self.errors.push(TypeError::new(span.clone(), message));
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::typeck;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::{env, process};
+
+    // Writes `files` to a fresh directory, and loads the first one as the
+    // root module.
+    pub fn load_files(files: &[(&str, &str)]) -> (Program, Vec<ParseError>, Vec<TypeError>) {
+        static DIRS: AtomicUsize = AtomicUsize::new(0);
+        let dir = env::temp_dir().join(format!(
+            "lang-test-{}-{}",
+            process::id(),
+            DIRS.fetch_add(1, Ordering::Relaxed)
+        ));
+        for (name, text) in files {
+            let file = dir.join(name);
+            fs::create_dir_all(file.parent().unwrap()).unwrap();
+            fs::write(file, text).unwrap();
+        }
+        let loaded = load(&dir.join(files[0].0), &mut Sources::new());
+        fs::remove_dir_all(&dir).unwrap();
+        loaded
+    }
+
+    // Loads and type checks `files`, and returns the errors and warnings as
+    // they are reported.
+    fn diagnostics(files: &[(&str, &str)]) -> Vec<String> {
+        let (mut program, parse_errors, mut errors) = load_files(files);
+        assert!(parse_errors.is_empty(), "{:?}", parse_errors);
+        if errors.is_empty() {
+            errors = typeck::check(&mut program);
+        }
+        errors.iter().map(|err| err.to_string()).collect()
+    }
+
+    const MAIN: &str =
+        "use a\nuse a::b::{g}\nfn f() -> int { 1 }\nfn main() -> int { a::f() + f() + g() }\n";
+
+    #[test]
+    fn namespaces() {
+        let files = [
+            ("main.sd", MAIN),
+            ("a.sd", "use a::b\nfn f() -> int { b::g() }\n"),
+            ("a/b.sd", "fn g() -> int { 3 }\n"),
+        ];
+        assert_eq!(diagnostics(&files), Vec::<String>::new());
+        let (program, _, _) = load_files(&files);
+        let paths: Vec<_> = program.modules.iter().map(|m| m.path.as_str()).collect();
+        assert_eq!(paths, ["a::b", "a", ""]);
+        let main = "use a\nfn main() -> int { a::h() }\n";
+        let files = [("main.sd", main), ("a.sd", "fn f() -> int { 1 }\n")];
+        assert_eq!(diagnostics(&files), ["cannot find value `h` in module `a`"]);
+    }
+
+    #[test]
+    fn cycles() {
+        let files = [
+            ("main.sd", MAIN),
+            ("a.sd", "use a::b\nfn f() -> int { b::g() }\n"),
+            ("a/b.sd", "use a\nfn g() -> int { 3 }\n"),
+        ];
+        assert_eq!(
+            diagnostics(&files),
+            ["cycle detected when loading module `a`"]
+        );
+        let files = [("main.sd", "use main\nfn main() -> int { 0 }\n")];
+        assert_eq!(
+            diagnostics(&files),
+            ["cycle detected when loading module `main`"]
+        );
+    }
+
+    #[test]
+    fn missing_module() {
+        let errors = diagnostics(&[("main.sd", "use a::c\nfn main() -> int { 0 }\n")]);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with("cannot find module `a::c`, expected the file `"));
+    }
+
+    #[test]
+    fn shards() {
+        let src = "struct P { x: int }\nfn rootfn() -> int { 1 }\nshard a {\n    fn f() -> int { rootfn() + b::g() }\n    fn mk() -> P { P { x: 1 } }\n}\nshard b {\n    fn g() -> int { a::f() }\n}\nfn main() -> int { a::mk().x }\n";
+        assert_eq!(
+            diagnostics(&[("main.sd", src)]),
+            [
+                "cannot use `rootfn` in shard `a`, as it belongs to no shard",
+                "cannot import `a::mk` from shard `a`, as values of type `P` cannot be passed between shards",
+                "cycle detected when importing shard `a` into shard `b`",
+            ]
+        );
+        let src = "shard a { fn f() {} }\nshard a { fn g() {} }\n";
+        assert_eq!(
+            diagnostics(&[("main.sd", src)]),
+            ["the shard `a` is defined multiple times"]
+        );
+    }
+
+    #[test]
+    fn local_items() {
+        let main = "use util\nshard math {\n    local const K: int = 2\n    local fn helper(x: int) -> int { x * K }\n    local fn dead() {}\n    local struct S { x: int }\n    fn twice(x: int) -> int { helper(x) }\n}\nfn main() -> int { math::twice(1) + math::helper(1) + util::hidden() }\n";
+        let util = "local fn hidden() -> int { 1 }\nlocal fn unused() {}\n";
+        assert_eq!(
+            diagnostics(&[("main.sd", main), ("util.sd", util)]),
+            [
+                "`math::helper` is local to shard `math`",
+                "`util::hidden` is local to module `util`",
+                "warning: function `math::dead` is never used",
+                "warning: struct `math::S` is never used",
+                "warning: function `util::hidden` is never used",
+                "warning: function `util::unused` is never used",
+            ]
+        );
+    }
+}
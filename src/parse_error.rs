@@ -1,12 +1,141 @@
+use super::token::{Span, Token};
+use super::token_kind::TokenKind;
 use std::fmt;
 
 pub type ParseResult<T> = std::result::Result<T, ParseError>;
 
-#[derive(Debug)]
-pub struct ParseError;
+#[derive(Debug, Clone)]
+pub struct ParseError {
+    pub span: Span,
+    // Token kinds that would have been accepted. Empty if the found token is
+    // itself malformed, such as an out-of-range integer literal.
+    pub expected: Vec<TokenKind>,
+    // What was expected if it is not a token, like the item a doc comment
+    // documents.
+    pub expected_what: Option<&'static str>,
+    pub found: Token,
+}
+
+impl ParseError {
+    pub fn new(expected: Vec<TokenKind>, found: Token) -> ParseError {
+        ParseError {
+            span: found.span(),
+            expected,
+            expected_what: None,
+            found,
+        }
+    }
+
+    // A doc comment followed by `found` rather than the `what` it documents.
+    pub fn after_doc(what: &'static str, found: Token) -> ParseError {
+        ParseError {
+            expected_what: Some(what),
+            ..ParseError::new(Vec::new(), found)
+        }
+    }
+
+    pub fn invalid(found: Token) -> ParseError {
+        ParseError::new(Vec::new(), found)
+    }
+
+    // Of two alternative failures, the one that got further into the input
+    // is usually the more helpful one.
+    pub fn furthest(self, other: ParseError) -> ParseError {
+        if other.span.start > self.span.start {
+            other
+        } else {
+            self
+        }
+    }
+}
 
 impl fmt::Display for ParseError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Parsing Error.")
+        let found = match self.found.kind() {
+            kind @ (TokenKind::Ident
+            | TokenKind::Int
+            | TokenKind::Float
+            | TokenKind::String
+            | TokenKind::Error) => {
+                format!("{} `{}`", kind, self.found.source_str().escape_debug())
+            }
+            kind => kind.to_string(),
+        };
+        if let Some(what) = self.expected_what {
+            return write!(f, "expected {} after doc comment, found {}", what, found);
+        }
+        match self.expected.as_slice() {
+            [] => write!(f, "invalid {}", found),
+            [kind] => write!(f, "expected {}, found {}", kind, found),
+            [first, second] => write!(f, "expected {} or {}, found {}", first, second, found),
+            [kinds @ .., last] => {
+                write!(f, "expected one of ")?;
+                for kind in kinds {
+                    write!(f, "{}, ", kind)?;
+                }
+                write!(f, "or {}, found {}", last, found)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+    use logos::Logos;
+
+    // The first token of `src`.
+    fn token(src: &str) -> Token {
+        Token::pop_from(&mut TokenKind::lexer(src)).unwrap()
+    }
+
+    #[test]
+    fn fields() {
+        let src = "const X int = 1\nfn main() -> int { let = 2 }\n";
+        let (_, errors) = Parser::new(TokenKind::lexer(src)).parse_items();
+        assert_eq!(errors.len(), 2, "{:?}", errors);
+        assert_eq!(errors[0].span, 8..11);
+        assert_eq!(errors[0].expected, [TokenKind::Colon]);
+        assert_eq!(errors[0].found.kind(), TokenKind::Ident);
+        assert_eq!(errors[0].found.source_str(), "int");
+        assert_eq!(&src[errors[1].span.clone()], "=");
+        assert_eq!(errors[1].expected, [TokenKind::Ident]);
+        assert_eq!(errors[1].found.kind(), TokenKind::Eq);
+    }
+
+    #[test]
+    fn display() {
+        use TokenKind::*;
+        let err = ParseError::new(vec![Colon], token("int"));
+        assert_eq!(err.to_string(), "expected `:`, found identifier `int`");
+        let err = ParseError::new(vec![Comma, RightParen], token("}"));
+        assert_eq!(err.to_string(), "expected `,` or `)`, found `}`");
+        let err = ParseError::new(vec![Comma, Semicolon, RightBrace], token("\n"));
+        assert_eq!(
+            err.to_string(),
+            "expected one of `,`, `;`, or `}`, found newline"
+        );
+        let err = ParseError::invalid(token("0b12"));
+        assert_eq!(err.to_string(), "invalid integer literal `0b12`");
+        let err = ParseError::after_doc("field", token("}"));
+        assert_eq!(
+            err.to_string(),
+            "expected field after doc comment, found `}`"
+        );
+    }
+
+    #[test]
+    fn furthest() {
+        let near = ParseError::new(vec![TokenKind::Colon], token("x"));
+        let far = ParseError {
+            span: 4..5,
+            ..ParseError::new(vec![TokenKind::Comma], token("y"))
+        };
+        assert_eq!(
+            near.clone().furthest(far.clone()).expected,
+            [TokenKind::Comma]
+        );
+        assert_eq!(far.furthest(near).expected, [TokenKind::Comma]);
     }
 }
@@ -1,33 +1,168 @@
 use super::ast::*;
 use super::parse_error::{ParseError, ParseResult};
 use super::precedence::Precedence;
+use super::token::Span;
 use super::token_buffer::TokenBuffer;
 use super::token_kind::TokenKind;
 use logos::Lexer;
 use std::fmt;
 
+// Tokens that can start an expression.
+const EXPR_START: [TokenKind; 19] = [
+    TokenKind::LeftBrace,
+    TokenKind::LeftParen,
+    TokenKind::If,
+    TokenKind::Match,
+    TokenKind::While,
+    TokenKind::Loop,
+    TokenKind::For,
+    TokenKind::Label,
+    TokenKind::Break,
+    TokenKind::Continue,
+    TokenKind::Return,
+    TokenKind::Minus,
+    TokenKind::Bang,
+    TokenKind::Ident,
+    TokenKind::Int,
+    TokenKind::Float,
+    TokenKind::String,
+    TokenKind::True,
+    TokenKind::False,
+];
+
+// Tokens that can start a top-level item.
+const ITEM_START: [TokenKind; 10] = [
+    TokenKind::Local,
+    TokenKind::Fn,
+    TokenKind::Extern,
+    TokenKind::Const,
+    TokenKind::Struct,
+    TokenKind::Enum,
+    TokenKind::Type,
+    TokenKind::Impl,
+    TokenKind::Use,
+    TokenKind::Shard,
+];
+
+// Items that can be marked `local`.
+const LOCAL_ITEM_START: [TokenKind; 3] = [TokenKind::Fn, TokenKind::Const, TokenKind::Struct];
+
+// Tokens that can start a pattern.
+const PATTERN_START: [TokenKind; 7] = [
+    TokenKind::Ident,
+    TokenKind::Minus,
+    TokenKind::Int,
+    TokenKind::Float,
+    TokenKind::String,
+    TokenKind::True,
+    TokenKind::False,
+];
+
+const BINOPS: [TokenKind; 18] = [
+    TokenKind::Plus,
+    TokenKind::Minus,
+    TokenKind::Star,
+    TokenKind::Slash,
+    TokenKind::Percent,
+    TokenKind::AndAnd,
+    TokenKind::OrOr,
+    TokenKind::Ampersand,
+    TokenKind::Bar,
+    TokenKind::Caret,
+    TokenKind::LtLt,
+    TokenKind::GtGt,
+    TokenKind::EqEq,
+    TokenKind::BangEq,
+    TokenKind::Lt,
+    TokenKind::LtEq,
+    TokenKind::Gt,
+    TokenKind::GtEq,
+];
+
 pub struct Parser<'a> {
     tokens: TokenBuffer<'a>,
+    errors: Vec<ParseError>,
+    // Set in the condition of an `if` or `while` and the range of a `for`,
+    // where `Name {` starts the body rather than a struct literal.
+    no_struct_literal: bool,
 }
 
 impl<'a> Parser<'a> {
     pub fn new(lexer: Lexer<'a, TokenKind>) -> Parser {
         Parser {
             tokens: TokenBuffer::new(lexer),
+            errors: Vec::new(),
+            no_struct_literal: false,
         }
     }
 
     fn skip_whitespace(&mut self) {
         loop {
             match self.tokens.get_token_kind(0) {
-                TokenKind::Whitespace => self.tokens.bump(),
+                TokenKind::Whitespace | TokenKind::LineComment | TokenKind::BlockComment => {
+                    self.tokens.bump()
+                }
                 _ => break,
             }
         }
     }
 
-    pub fn parse_block_expr(&mut self) -> ParseResult<Expr> {
+    fn skip_newlines(&mut self) {
+        loop {
+            self.skip_whitespace();
+            match self.tokens.get_token_kind(0) {
+                TokenKind::Newline => self.tokens.bump(),
+                _ => break,
+            }
+        }
+    }
+
+    // Collects the `///` comments in front of a declaration, one line each.
+    fn parse_doc(&mut self) -> Option<String> {
+        let mut lines: Vec<String> = Vec::new();
+        loop {
+            self.skip_whitespace();
+            match self.tokens.get_token_kind(0) {
+                TokenKind::DocComment => {
+                    let src = self.tokens.get_token(0).source_str();
+                    let line = src[3..].strip_prefix(' ').unwrap_or(&src[3..]);
+                    lines.push(line.to_string());
+                    self.tokens.bump();
+                }
+                TokenKind::Newline if !lines.is_empty() => self.tokens.bump(),
+                _ => break,
+            }
+        }
+        if lines.is_empty() {
+            None
+        } else {
+            Some(lines.join("\n"))
+        }
+    }
+
+    // Reports a doc comment at the end of a block or of the input, where
+    // there is no `what` to document.
+    fn expect_after_doc(&mut self, doc: &Option<String>, what: &'static str) -> ParseResult<()> {
+        match self.tokens.get_token_kind(0) {
+            TokenKind::RightBrace | TokenKind::Eof if doc.is_some() => {
+                let tkn = self.tokens.get_token(0).clone();
+                Err(ParseError::after_doc(what, tkn))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn start_pos(&mut self) -> usize {
         self.skip_whitespace();
+        self.tokens.get_token(0).span().start
+    }
+
+    fn span_from(&self, start: usize) -> Span {
+        start..self.tokens.prev_end().max(start)
+    }
+
+    pub fn parse_block_expr(&mut self) -> ParseResult<Expr> {
+        let start = self.start_pos();
         self.tokens.assert_token(0, TokenKind::LeftBrace)?;
         self.skip_whitespace();
         let mut stmt_list = Vec::new();
@@ -39,36 +174,550 @@ impl<'a> Parser<'a> {
                 }
                 TokenKind::RightBrace => {
                     self.tokens.bump();
+                    let span = self.span_from(start);
                     self.skip_whitespace();
-                    return Ok(Expr::Block(stmt_list));
+                    return Ok(Expr::new(ExprKind::Block(stmt_list), span));
+                }
+                TokenKind::Eof => {
+                    let tkn = self.tokens.get_token(0).clone();
+                    self.errors
+                        .push(ParseError::new(vec![TokenKind::RightBrace], tkn));
+                    let span = self.span_from(start);
+                    return Ok(Expr::new(ExprKind::Block(stmt_list), span));
                 }
                 _ => {
-                    let stmt = self.parse_stmt()?;
-                    stmt_list.push(stmt);
+                    let stmt_start = self.start_pos();
+                    match self.parse_stmt() {
+                        Ok(stmt) => stmt_list.push(stmt),
+                        Err(err) => {
+                            self.errors.push(err);
+                            self.synchronize();
+                            let span = self.span_from(stmt_start);
+                            stmt_list.push(Stmt::Expr(Expr::new(ExprKind::Error, span)));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // Skips ahead to the end of the broken statement: the next newline, `;`
+    // or `}` outside of any nested brackets. The token itself is not consumed.
+    fn synchronize(&mut self) {
+        let mut depth = 0;
+        loop {
+            match self.tokens.get_token_kind(0) {
+                TokenKind::Eof => return,
+                TokenKind::Newline | TokenKind::Semicolon | TokenKind::RightBrace if depth == 0 => {
+                    return
+                }
+                TokenKind::LeftBrace | TokenKind::LeftParen | TokenKind::LeftBracket => depth += 1,
+                TokenKind::RightBrace | TokenKind::RightParen | TokenKind::RightBracket => {
+                    depth = (depth - 1).max(0)
+                }
+                _ => (),
+            }
+            self.tokens.bump();
+        }
+    }
+
+    // Parses the items of a source file. Syntax errors do not stop the
+    // parser: broken items are skipped, and broken statements become
+    // `ExprKind::Error` nodes.
+    pub fn parse_items(&mut self) -> (Vec<Item>, Vec<ParseError>) {
+        let items = self.parse_item_list(false);
+        (items, std::mem::take(&mut self.errors))
+    }
+
+    // Parses items up to the end of the input, or up to the `}` closing a
+    // shard if `nested`.
+    fn parse_item_list(&mut self, nested: bool) -> Vec<Item> {
+        let mut items = Vec::new();
+        loop {
+            self.skip_newlines();
+            match self.tokens.get_token_kind(0) {
+                TokenKind::Eof => break,
+                TokenKind::RightBrace if nested => break,
+                TokenKind::Semicolon => self.tokens.bump(),
+                _ => match self.parse_item() {
+                    Ok(item) => items.push(item),
+                    Err(err) => {
+                        self.errors.push(err);
+                        self.synchronize();
+                        // A stray `}` would stop the synchronization forever.
+                        if self.tokens.get_token_kind(0) == TokenKind::RightBrace && !nested {
+                            self.tokens.bump();
+                        }
+                    }
+                },
+            }
+        }
+        items
+    }
+
+    pub fn parse_item(&mut self) -> ParseResult<Item> {
+        let doc = self.parse_doc();
+        let local = self.parse_local();
+        match self.tokens.get_token_kind(0) {
+            TokenKind::Fn => Ok(Item::Fn(self.parse_fn_decl(doc, local, None)?)),
+            TokenKind::Const => Ok(Item::Const(self.parse_const_decl(doc, local)?)),
+            TokenKind::Struct => Ok(Item::Struct(self.parse_struct_decl(doc, local)?)),
+            _ if local => {
+                let tkn = self.tokens.get_token(0).clone();
+                Err(ParseError::new(LOCAL_ITEM_START.to_vec(), tkn))
+            }
+            TokenKind::Extern => Ok(Item::Extern(self.parse_extern_fn(doc)?)),
+            TokenKind::Enum => Ok(Item::Enum(self.parse_enum_decl(doc)?)),
+            TokenKind::Type => Ok(Item::Alias(self.parse_alias_decl(doc)?)),
+            TokenKind::Impl => Ok(Item::Impl(self.parse_impl_decl(doc)?)),
+            TokenKind::Use => Ok(Item::Use(self.parse_use_decl(doc)?)),
+            TokenKind::Shard => Ok(Item::Shard(self.parse_shard_decl(doc)?)),
+            _ => {
+                let tkn = self.tokens.get_token(0).clone();
+                match doc {
+                    Some(_) => Err(ParseError::after_doc("item", tkn)),
+                    None => Err(ParseError::new(ITEM_START.to_vec(), tkn)),
                 }
             }
         }
     }
 
-    pub fn parse_program(&mut self) -> ParseResult<Program> {
-        let stmt = self.parse_stmt()?;
-        Ok(Program { stmt })
+    // Parses the `local` modifier of an item, if any.
+    fn parse_local(&mut self) -> bool {
+        self.skip_whitespace();
+        if self.tokens.get_token_kind(0) != TokenKind::Local {
+            return false;
+        }
+        self.tokens.bump();
+        self.skip_whitespace();
+        true
     }
 
     pub fn parse_stmt(&mut self) -> ParseResult<Stmt> {
+        let doc = self.parse_doc();
+        self.expect_after_doc(&doc, "statement")?;
         match self.tokens.get_token_kind(0) {
-            TokenKind::Let => self.parse_decl_stmt(),
-            _ => self.parse_expr_stmt(),
+            TokenKind::Let => self.parse_decl_stmt(doc),
+            TokenKind::Fn => Ok(Stmt::Fn(self.parse_fn_decl(doc, false, None)?)),
+            kind => {
+                // Only declarations are documented. The comment is reported
+                // and the statement parsed all the same.
+                if doc.is_some() {
+                    let tkn = self.tokens.get_token(0).clone();
+                    let err = ParseError::after_doc("`let` or `fn`", tkn);
+                    self.errors.push(err);
+                }
+                match kind {
+                    TokenKind::Ident => match self.parse_assign_stmt()? {
+                        Some(assign) => Ok(assign),
+                        None => self.parse_expr_stmt(),
+                    },
+                    _ => self.parse_expr_stmt(),
+                }
+            }
         }
     }
 
-    pub fn parse_decl_stmt(&mut self) -> ParseResult<Stmt> {
+    pub fn parse_decl_stmt(&mut self, doc: Option<String>) -> ParseResult<Stmt> {
         self.skip_whitespace();
         self.tokens.assert_token(0, TokenKind::Let)?;
-        let var_name = self.parse_ident()?;
+        self.skip_whitespace();
+        let mutable = self.tokens.get_token_kind(0) == TokenKind::Mut;
+        if mutable {
+            self.tokens.bump();
+        }
+        let name = self.parse_ident()?;
+        let typ = match self.tokens.get_token_kind(0) {
+            TokenKind::Colon => {
+                self.tokens.bump();
+                Some(self.parse_type()?)
+            }
+            _ => None,
+        };
+        self.tokens.assert_token(0, TokenKind::Eq)?;
+        let expr = self.parse_expr(Precedence::Lowest)?;
+        Ok(Stmt::Decl(Decl {
+            doc,
+            mutable,
+            name,
+            typ,
+            expr,
+        }))
+    }
+
+    // Parses a function item. In an `impl` block for `self_type`, the first
+    // argument may be a lone `self` of that type.
+    pub fn parse_fn_decl(
+        &mut self,
+        doc: Option<String>,
+        local: bool,
+        self_type: Option<&Ident>,
+    ) -> ParseResult<FnDecl> {
+        let start = self.start_pos();
+        self.tokens.assert_token(0, TokenKind::Fn)?;
+        let name = self.parse_ident()?;
+        self.tokens.assert_token(0, TokenKind::LeftParen)?;
+        let mut args = Vec::new();
+        if let Some(self_type) = self_type {
+            args.extend(self.parse_receiver(self_type)?);
+        }
+        args.extend(self.parse_arg_list()?);
+        self.tokens.assert_token(0, TokenKind::RightParen)?;
+        let ret = self.parse_return_type()?;
+        self.skip_whitespace();
+        let body = self.parse_block_expr()?;
+        let span = self.span_from(start);
+        Ok(FnDecl {
+            doc,
+            local,
+            name,
+            args,
+            ret,
+            body,
+            span,
+            typ: None,
+        })
+    }
+
+    pub fn parse_const_decl(&mut self, doc: Option<String>, local: bool) -> ParseResult<ConstDecl> {
+        let start = self.start_pos();
+        self.tokens.assert_token(0, TokenKind::Const)?;
+        let name = self.parse_ident()?;
+        self.tokens.assert_token(0, TokenKind::Colon)?;
+        let typ = self.parse_type()?;
         self.tokens.assert_token(0, TokenKind::Eq)?;
         let expr = self.parse_expr(Precedence::Lowest)?;
-        Ok(Stmt::Decl(var_name, expr))
+        let span = self.span_from(start);
+        Ok(ConstDecl {
+            doc,
+            local,
+            name,
+            typ,
+            expr,
+            span,
+            value: None,
+        })
+    }
+
+    pub fn parse_impl_decl(&mut self, doc: Option<String>) -> ParseResult<ImplDecl> {
+        let start = self.start_pos();
+        self.tokens.assert_token(0, TokenKind::Impl)?;
+        let name = self.parse_ident()?;
+        self.tokens.assert_token(0, TokenKind::LeftBrace)?;
+        let mut fns = Vec::new();
+        loop {
+            self.skip_newlines();
+            if self.tokens.get_token_kind(0) == TokenKind::RightBrace {
+                break;
+            }
+            let doc = self.parse_doc();
+            self.expect_after_doc(&doc, "function")?;
+            let local = self.parse_local();
+            fns.push(self.parse_fn_decl(doc, local, Some(&name))?);
+        }
+        self.tokens.assert_token(0, TokenKind::RightBrace)?;
+        let span = self.span_from(start);
+        Ok(ImplDecl {
+            doc,
+            name,
+            fns,
+            span,
+        })
+    }
+
+    pub fn parse_shard_decl(&mut self, doc: Option<String>) -> ParseResult<ShardDecl> {
+        let start = self.start_pos();
+        self.tokens.assert_token(0, TokenKind::Shard)?;
+        let name = self.parse_ident()?;
+        self.tokens.assert_token(0, TokenKind::LeftBrace)?;
+        let items = self.parse_item_list(true);
+        self.tokens.assert_token(0, TokenKind::RightBrace)?;
+        let span = self.span_from(start);
+        Ok(ShardDecl {
+            doc,
+            name,
+            items,
+            span,
+        })
+    }
+
+    // Parses `self` as the first argument of a method, followed by a comma
+    // unless it is the only one.
+    fn parse_receiver(&mut self, self_type: &Ident) -> ParseResult<Option<Arg>> {
+        self.skip_whitespace();
+        let tkn = self.tokens.get_token(0).clone();
+        if tkn.kind() != TokenKind::Ident || tkn.source_str() != "self" {
+            return Ok(None);
+        }
+        let snap = self.tokens.save_snap();
+        self.tokens.bump();
+        self.skip_whitespace();
+        match self.tokens.get_token_kind(0) {
+            TokenKind::Comma => self.tokens.bump(),
+            TokenKind::RightParen => (),
+            // `self: Type` is an ordinary argument.
+            _ => {
+                self.tokens.return_snap(snap);
+                return Ok(None);
+            }
+        }
+        let typ = TypeExpr {
+            kind: TypeExprKind::Name(self_type.clone(), Vec::new()),
+            span: tkn.span(),
+        };
+        Ok(Some(Arg {
+            name: tkn.source_str(),
+            typ,
+        }))
+    }
+
+    pub fn parse_use_decl(&mut self, doc: Option<String>) -> ParseResult<UseDecl> {
+        let start = self.start_pos();
+        self.tokens.assert_token(0, TokenKind::Use)?;
+        let mut path = vec![self.parse_ident()?];
+        let mut names = None;
+        while self.tokens.get_token_kind(0) == TokenKind::ColonColon {
+            self.tokens.bump();
+            self.skip_whitespace();
+            if self.tokens.get_token_kind(0) != TokenKind::LeftBrace {
+                path.push(self.parse_ident()?);
+                continue;
+            }
+            self.tokens.bump();
+            let mut list = Vec::new();
+            loop {
+                self.skip_newlines();
+                if self.tokens.get_token_kind(0) == TokenKind::RightBrace {
+                    break;
+                }
+                let start = self.start_pos();
+                let name = self.parse_ident()?;
+                list.push((name, self.span_from(start)));
+                match self.tokens.get_token_kind(0) {
+                    TokenKind::Comma => self.tokens.bump(),
+                    _ => break,
+                }
+            }
+            self.skip_newlines();
+            self.tokens.assert_token(0, TokenKind::RightBrace)?;
+            names = Some(list);
+            break;
+        }
+        let span = self.span_from(start);
+        Ok(UseDecl {
+            doc,
+            path,
+            names,
+            span,
+        })
+    }
+
+    pub fn parse_alias_decl(&mut self, doc: Option<String>) -> ParseResult<TypeAlias> {
+        let start = self.start_pos();
+        self.tokens.assert_token(0, TokenKind::Type)?;
+        let name = self.parse_ident()?;
+        let mut params = Vec::new();
+        if self.tokens.get_token_kind(0) == TokenKind::Lt {
+            self.tokens.bump();
+            loop {
+                self.skip_whitespace();
+                if self.tokens.get_token_kind(0) == TokenKind::Gt {
+                    break;
+                }
+                params.push(self.parse_ident()?);
+                match self.tokens.get_token_kind(0) {
+                    TokenKind::Comma => self.tokens.bump(),
+                    _ => break,
+                }
+            }
+            self.tokens.assert_token(0, TokenKind::Gt)?;
+            self.skip_whitespace();
+        }
+        self.tokens.assert_token(0, TokenKind::Eq)?;
+        self.skip_whitespace();
+        let typ = self.parse_type()?;
+        let span = self.span_from(start);
+        Ok(TypeAlias {
+            doc,
+            name,
+            params,
+            typ,
+            span,
+        })
+    }
+
+    pub fn parse_struct_decl(
+        &mut self,
+        doc: Option<String>,
+        local: bool,
+    ) -> ParseResult<StructDecl> {
+        let start = self.start_pos();
+        self.tokens.assert_token(0, TokenKind::Struct)?;
+        let name = self.parse_ident()?;
+        let fields = self.parse_fields()?;
+        let span = self.span_from(start);
+        Ok(StructDecl {
+            doc,
+            local,
+            name,
+            fields,
+            span,
+        })
+    }
+
+    // Parses `{ field: Type, ... }`, with the fields separated by commas or
+    // newlines.
+    pub fn parse_fields(&mut self) -> ParseResult<Vec<Field>> {
+        self.tokens.assert_token(0, TokenKind::LeftBrace)?;
+        let mut fields = Vec::new();
+        loop {
+            self.skip_newlines();
+            let doc = self.parse_doc();
+            self.expect_after_doc(&doc, "field")?;
+            if self.tokens.get_token_kind(0) == TokenKind::RightBrace {
+                break;
+            }
+            let field_start = self.start_pos();
+            let field = self.parse_ident()?;
+            self.tokens.assert_token(0, TokenKind::Colon)?;
+            let typ = self.parse_type()?;
+            fields.push(Field {
+                doc,
+                name: field,
+                typ,
+                span: self.span_from(field_start),
+            });
+            match self.tokens.get_token_kind(0) {
+                TokenKind::Comma | TokenKind::Newline => self.tokens.bump(),
+                TokenKind::RightBrace => break,
+                _ => {
+                    let tkn = self.tokens.get_token(0).clone();
+                    let expected = vec![TokenKind::Comma, TokenKind::RightBrace];
+                    return Err(ParseError::new(expected, tkn));
+                }
+            }
+        }
+        self.tokens.assert_token(0, TokenKind::RightBrace)?;
+        Ok(fields)
+    }
+
+    pub fn parse_enum_decl(&mut self, doc: Option<String>) -> ParseResult<EnumDecl> {
+        let start = self.start_pos();
+        self.tokens.assert_token(0, TokenKind::Enum)?;
+        let name = self.parse_ident()?;
+        self.tokens.assert_token(0, TokenKind::LeftBrace)?;
+        let mut variants = Vec::new();
+        loop {
+            self.skip_newlines();
+            let doc = self.parse_doc();
+            self.expect_after_doc(&doc, "variant")?;
+            if self.tokens.get_token_kind(0) == TokenKind::RightBrace {
+                break;
+            }
+            let variant_start = self.start_pos();
+            let variant = self.parse_ident()?;
+            let fields = match self.tokens.get_token_kind(0) {
+                TokenKind::LeftParen => {
+                    self.tokens.bump();
+                    let mut types = Vec::new();
+                    loop {
+                        self.skip_newlines();
+                        if self.tokens.get_token_kind(0) == TokenKind::RightParen {
+                            break;
+                        }
+                        types.push(self.parse_type()?);
+                        self.skip_newlines();
+                        match self.tokens.get_token_kind(0) {
+                            TokenKind::Comma => self.tokens.bump(),
+                            _ => break,
+                        }
+                    }
+                    self.tokens.assert_token(0, TokenKind::RightParen)?;
+                    VariantFields::Tuple(types)
+                }
+                TokenKind::LeftBrace => VariantFields::Struct(self.parse_fields()?),
+                _ => VariantFields::Unit,
+            };
+            variants.push(Variant {
+                doc,
+                name: variant,
+                fields,
+                span: self.span_from(variant_start),
+            });
+            self.skip_whitespace();
+            match self.tokens.get_token_kind(0) {
+                TokenKind::Comma | TokenKind::Newline => self.tokens.bump(),
+                TokenKind::RightBrace => break,
+                _ => {
+                    let tkn = self.tokens.get_token(0).clone();
+                    let expected = vec![TokenKind::Comma, TokenKind::RightBrace];
+                    return Err(ParseError::new(expected, tkn));
+                }
+            }
+        }
+        self.tokens.assert_token(0, TokenKind::RightBrace)?;
+        let span = self.span_from(start);
+        Ok(EnumDecl {
+            doc,
+            name,
+            variants,
+            span,
+        })
+    }
+
+    pub fn parse_extern_fn(&mut self, doc: Option<String>) -> ParseResult<ExternFn> {
+        let start = self.start_pos();
+        self.tokens.assert_token(0, TokenKind::Extern)?;
+        self.skip_whitespace();
+        self.tokens.assert_token(0, TokenKind::Fn)?;
+        let name = self.parse_ident()?;
+        self.tokens.assert_token(0, TokenKind::LeftParen)?;
+        let args = self.parse_arg_list()?;
+        self.tokens.assert_token(0, TokenKind::RightParen)?;
+        let ret = self.parse_return_type()?;
+        let span = self.span_from(start);
+        Ok(ExternFn {
+            doc,
+            name,
+            args,
+            ret,
+            span,
+            typ: None,
+        })
+    }
+
+    // Parses an assignment if the statement is one, and otherwise leaves
+    // the tokens untouched.
+    pub fn parse_assign_stmt(&mut self) -> ParseResult<Option<Stmt>> {
+        let start = self.start_pos();
+        let snap = self.tokens.save_snap();
+        let name = self.parse_ident()?;
+        let mut target = Expr::new(ExprKind::Var(name), self.span_from(start));
+        while self.tokens.get_token_kind(0) == TokenKind::Period || self.at_tuple_index() {
+            let field = self.parse_member()?;
+            let span = self.span_from(start);
+            target = Expr::new(ExprKind::Field(Box::new(target), field), span);
+        }
+        let op = match self.tokens.get_token_kind(0) {
+            TokenKind::Eq => None,
+            TokenKind::PlusEq => Some(BinOp::Add),
+            TokenKind::MinusEq => Some(BinOp::Minus),
+            TokenKind::StarEq => Some(BinOp::Times),
+            TokenKind::SlashEq => Some(BinOp::Divide),
+            _ => {
+                self.tokens.return_snap(snap);
+                return Ok(None);
+            }
+        };
+        self.tokens.bump();
+        let expr = self.parse_expr(Precedence::Lowest)?;
+        let span = self.span_from(start);
+        Ok(Some(Stmt::Assign(Assign {
+            target,
+            op,
+            expr,
+            span,
+        })))
     }
 
     pub fn parse_expr_stmt(&mut self) -> ParseResult<Stmt> {
@@ -83,8 +732,128 @@ impl<'a> Parser<'a> {
         Ok(tkn.source_str())
     }
 
-    pub fn parse_typename(&mut self) -> ParseResult<Ident> {
-        self.parse_ident()
+    // The rest of a path whose first segment is `name`, like `a::b::C`.
+    // Returns the path up to its last segment, and the last segment if
+    // there are several.
+    fn parse_path(&mut self, name: Ident) -> ParseResult<(Ident, Option<Ident>)> {
+        let mut segments = vec![name];
+        while self.tokens.get_token_kind(0) == TokenKind::ColonColon {
+            self.tokens.bump();
+            segments.push(self.parse_ident()?);
+        }
+        let last = segments.pop().unwrap();
+        match segments.is_empty() {
+            true => Ok((last, None)),
+            false => Ok((segments.join("::"), Some(last))),
+        }
+    }
+
+    pub fn parse_type(&mut self) -> ParseResult<TypeExpr> {
+        let start = self.start_pos();
+        let tkn = self.tokens.get_token(0).clone();
+        let kind = match tkn.kind() {
+            TokenKind::Ident => {
+                let name = self.parse_ident()?;
+                let name = match self.parse_path(name)? {
+                    (path, Some(last)) => format!("{}::{}", path, last),
+                    (name, None) => name,
+                };
+                let args = match self.tokens.get_token_kind(0) {
+                    TokenKind::Lt => self.parse_type_args()?,
+                    _ => Vec::new(),
+                };
+                TypeExprKind::Name(name, args)
+            }
+            // `()`, a tuple, or a type in parentheses.
+            TokenKind::LeftParen => {
+                self.tokens.bump();
+                let mut types = Vec::new();
+                let mut trailing_comma = false;
+                loop {
+                    self.skip_whitespace();
+                    if self.tokens.get_token_kind(0) == TokenKind::RightParen {
+                        break;
+                    }
+                    types.push(self.parse_type()?);
+                    trailing_comma = self.tokens.get_token_kind(0) == TokenKind::Comma;
+                    match trailing_comma {
+                        true => self.tokens.bump(),
+                        false => break,
+                    }
+                }
+                self.tokens.assert_token(0, TokenKind::RightParen)?;
+                match types.len() {
+                    0 => TypeExprKind::Unit,
+                    1 if !trailing_comma => types.pop().unwrap().kind,
+                    _ => TypeExprKind::Tuple(types),
+                }
+            }
+            TokenKind::Fn => {
+                self.tokens.bump();
+                self.skip_whitespace();
+                self.tokens.assert_token(0, TokenKind::LeftParen)?;
+                let mut params = Vec::new();
+                loop {
+                    self.skip_whitespace();
+                    if self.tokens.get_token_kind(0) == TokenKind::RightParen {
+                        break;
+                    }
+                    params.push(self.parse_type()?);
+                    match self.tokens.get_token_kind(0) {
+                        TokenKind::Comma => self.tokens.bump(),
+                        _ => break,
+                    }
+                }
+                self.tokens.assert_token(0, TokenKind::RightParen)?;
+                let ret = self.parse_return_type()?.map(Box::new);
+                TypeExprKind::Fn(params, ret)
+            }
+            _ => {
+                let expected = vec![TokenKind::Ident, TokenKind::LeftParen, TokenKind::Fn];
+                return Err(ParseError::new(expected, tkn));
+            }
+        };
+        let span = self.span_from(start);
+        self.skip_whitespace();
+        Ok(TypeExpr { kind, span })
+    }
+
+    // Parses `<Type, ...>`. A `>>` closing nested arguments is read as two
+    // `>`.
+    fn parse_type_args(&mut self) -> ParseResult<Vec<TypeExpr>> {
+        self.tokens.assert_token(0, TokenKind::Lt)?;
+        let mut args = Vec::new();
+        loop {
+            self.skip_whitespace();
+            if matches!(
+                self.tokens.get_token_kind(0),
+                TokenKind::Gt | TokenKind::GtGt
+            ) {
+                break;
+            }
+            args.push(self.parse_type()?);
+            match self.tokens.get_token_kind(0) {
+                TokenKind::Comma => self.tokens.bump(),
+                _ => break,
+            }
+        }
+        match self.tokens.get_token_kind(0) {
+            TokenKind::GtGt => self.tokens.split_token(TokenKind::Gt),
+            _ => {
+                self.tokens.assert_token(0, TokenKind::Gt)?;
+            }
+        }
+        Ok(args)
+    }
+
+    // Parses an optional `-> Type`.
+    pub fn parse_return_type(&mut self) -> ParseResult<Option<TypeExpr>> {
+        self.skip_whitespace();
+        if self.tokens.get_token_kind(0) != TokenKind::Arrow {
+            return Ok(None);
+        }
+        self.tokens.bump();
+        Ok(Some(self.parse_type()?))
     }
 
     pub fn parse_arg(&mut self) -> ParseResult<Arg> {
@@ -93,62 +862,106 @@ impl<'a> Parser<'a> {
         self.skip_whitespace();
         self.tokens.assert_token(0, TokenKind::Colon)?;
         self.skip_whitespace();
-        let typename = self.parse_typename()?;
-        self.skip_whitespace();
-        Ok(Arg { name, typename })
+        let typ = self.parse_type()?;
+        Ok(Arg { name, typ })
     }
 
     pub fn parse_arg_list(&mut self) -> ParseResult<Vec<Arg>> {
         self.skip_whitespace();
         let mut arg_list = Vec::new();
         loop {
-            match self.parse_arg() {
-                Ok(arg) => {
-                    arg_list.push(arg);
-                    match self.tokens.get_token_kind(0) {
-                        TokenKind::Comma => self.tokens.bump(),
-                        _ => return Ok(arg_list),
-                    }
-                }
-                Err(_) => return Ok(arg_list),
+            self.skip_whitespace();
+            if self.tokens.get_token_kind(0) == TokenKind::RightParen {
+                return Ok(arg_list);
+            }
+            arg_list.push(self.parse_arg()?);
+            match self.tokens.get_token_kind(0) {
+                TokenKind::Comma => self.tokens.bump(),
+                _ => return Ok(arg_list),
             }
         }
     }
 
     pub fn parse_fn(&mut self) -> ParseResult<Expr> {
-        self.skip_whitespace();
+        let start = self.start_pos();
         self.tokens.assert_token(0, TokenKind::LeftParen)?;
         let args = self.parse_arg_list()?;
         self.tokens.assert_token(0, TokenKind::RightParen)?;
+        let ret = self.parse_return_type()?;
         self.skip_whitespace();
-        self.tokens.assert_token(0, TokenKind::Eq)?;
-        self.tokens.assert_token(0, TokenKind::Gt)?;
+        self.tokens.assert_token(0, TokenKind::FatArrow)?;
         let expr = self.parse_expr(Precedence::Lowest)?;
-        Ok(Expr::Fn(args, Box::new(expr)))
+        let span = self.span_from(start);
+        Ok(Expr::new(ExprKind::Fn(args, ret, Box::new(expr)), span))
     }
 
     pub fn parse_expr(&mut self, precedence: Precedence) -> ParseResult<Expr> {
         self.skip_whitespace();
         let prefix = self.tokens.get_token(0);
+        let span = prefix.span();
         let mut prev = match prefix.kind() {
             TokenKind::LeftBrace => self.parse_block_expr()?,
+            TokenKind::If => self.parse_if_expr()?,
+            TokenKind::Match => self.parse_match_expr()?,
+            TokenKind::While | TokenKind::Loop | TokenKind::For | TokenKind::Label => {
+                self.parse_loop_expr()?
+            }
+            TokenKind::Break | TokenKind::Continue | TokenKind::Return => self.parse_jump_expr()?,
             TokenKind::LeftParen => {
                 let snap = self.tokens.save_snap();
+                let error_count = self.errors.len();
                 match self.parse_fn() {
                     Ok(expr) => expr,
-                    Err(_) => {
+                    Err(fn_err) => {
                         self.tokens.return_snap(snap);
-                        self.parse_grouped_expr()?
+                        self.errors.truncate(error_count);
+                        self.parse_grouped_expr()
+                            .map_err(|err| fn_err.furthest(err))?
                     }
                 }
             }
             TokenKind::Int => {
-                let val = prefix.source_str().parse().expect("Not an integer?");
-                let res = Expr::Literal(Literal::Int(val));
+                let lit = parse_int(&prefix.source_str());
+                let lit = lit.ok_or_else(|| ParseError::invalid(prefix.clone()))?;
                 self.tokens.bump();
-                res
+                Expr::new(ExprKind::Literal(lit), span)
             }
-            _ => return Err(ParseError),
+            TokenKind::Float => {
+                let lit = parse_float(&prefix.source_str());
+                let lit = lit.ok_or_else(|| ParseError::invalid(prefix.clone()))?;
+                self.tokens.bump();
+                Expr::new(ExprKind::Literal(lit), span)
+            }
+            TokenKind::String => {
+                let src = prefix.source_str();
+                let val = unescape(&src[1..src.len() - 1])
+                    .ok_or_else(|| ParseError::invalid(prefix.clone()))?;
+                self.tokens.bump();
+                Expr::new(ExprKind::Literal(Literal::String(val)), span)
+            }
+            TokenKind::True | TokenKind::False => {
+                let lit = Literal::Bool(prefix.kind() == TokenKind::True);
+                self.tokens.bump();
+                Expr::new(ExprKind::Literal(lit), span)
+            }
+            TokenKind::Minus | TokenKind::Bang => self.parse_unop_expr()?,
+            TokenKind::Ident => {
+                let name = prefix.source_str();
+                self.tokens.bump();
+                self.skip_whitespace();
+                let (name, variant) = self.parse_path(name)?;
+                if self.tokens.get_token_kind(0) == TokenKind::LeftBrace && !self.no_struct_literal
+                {
+                    self.parse_struct_expr(name, variant, span.start)?
+                } else {
+                    let span = self.span_from(span.start);
+                    match variant {
+                        Some(variant) => Expr::new(ExprKind::Path(name, variant), span),
+                        None => Expr::new(ExprKind::Var(name), span),
+                    }
+                }
+            }
+            _ => return Err(ParseError::new(EXPR_START.to_vec(), prefix.clone())),
         };
 
         loop {
@@ -156,25 +969,285 @@ impl<'a> Parser<'a> {
             let next_token = self.tokens.get_token(0);
             let kind = next_token.kind();
             match kind {
-                TokenKind::Plus | TokenKind::Minus | TokenKind::Star | TokenKind::Slash => {
-                    if Precedence::from(&kind) > precedence {
-                        prev = self.parse_binop_expr(prev)?
-                    } else {
-                        return Ok(prev);
-                    }
+                _ if BINOPS.contains(&kind) && Precedence::from(&kind) > precedence => {
+                    prev = self.parse_binop_expr(prev)?
+                }
+                TokenKind::LeftParen if Precedence::from(&kind) > precedence => {
+                    prev = self.parse_call_expr(prev)?
+                }
+                TokenKind::Period if Precedence::from(&kind) > precedence => {
+                    prev = self.parse_field_expr(prev)?
+                }
+                TokenKind::Float if self.at_tuple_index() && Precedence::Call > precedence => {
+                    prev = self.parse_field_expr(prev)?
                 }
                 _ => return Ok(prev),
             }
         }
     }
 
+    // `(expr)`, or a tuple `(expr, ...)`.
     pub fn parse_grouped_expr(&mut self) -> ParseResult<Expr> {
+        let start = self.start_pos();
         self.tokens.assert_token(0, TokenKind::LeftParen)?;
-        let expr = self.parse_expr(Precedence::Lowest);
+        let expr = self.parse_nested_expr(false)?;
+        self.skip_whitespace();
+        if self.tokens.get_token_kind(0) != TokenKind::Comma {
+            self.tokens.assert_token(0, TokenKind::RightParen)?;
+            return Ok(expr);
+        }
+        let mut exprs = vec![expr];
+        while self.tokens.get_token_kind(0) == TokenKind::Comma {
+            self.tokens.bump();
+            self.skip_whitespace();
+            if self.tokens.get_token_kind(0) == TokenKind::RightParen {
+                break;
+            }
+            exprs.push(self.parse_nested_expr(false)?);
+            self.skip_whitespace();
+        }
         self.tokens.assert_token(0, TokenKind::RightParen)?;
+        let span = self.span_from(start);
+        Ok(Expr::new(ExprKind::Tuple(exprs), span))
+    }
+
+    // Parses an expression with struct literals allowed or not, as in the
+    // condition of an `if`.
+    fn parse_nested_expr(&mut self, no_struct_literal: bool) -> ParseResult<Expr> {
+        let outer = std::mem::replace(&mut self.no_struct_literal, no_struct_literal);
+        let expr = self.parse_expr(Precedence::Lowest);
+        self.no_struct_literal = outer;
         expr
     }
 
+    // `Name { field: expr, ... }`, where a lone `field` is short for
+    // `field: field`. The name, and variant if any, have already been
+    // consumed.
+    pub fn parse_struct_expr(
+        &mut self,
+        name: Ident,
+        variant: Option<Ident>,
+        start: usize,
+    ) -> ParseResult<Expr> {
+        self.tokens.assert_token(0, TokenKind::LeftBrace)?;
+        let mut fields = Vec::new();
+        loop {
+            self.skip_newlines();
+            // The fields of a struct are documented where it is declared.
+            if self.parse_doc().is_some() {
+                let tkn = self.tokens.get_token(0).clone();
+                self.errors.push(ParseError::after_doc("declaration", tkn));
+            }
+            if self.tokens.get_token_kind(0) == TokenKind::RightBrace {
+                break;
+            }
+            let field_start = self.start_pos();
+            let field = self.parse_ident()?;
+            let expr = match self.tokens.get_token_kind(0) {
+                TokenKind::Colon => {
+                    self.tokens.bump();
+                    self.parse_nested_expr(false)?
+                }
+                _ => Expr::new(ExprKind::Var(field.clone()), self.span_from(field_start)),
+            };
+            fields.push((field, expr));
+            self.skip_newlines();
+            match self.tokens.get_token_kind(0) {
+                TokenKind::Comma => self.tokens.bump(),
+                _ => break,
+            }
+        }
+        self.skip_newlines();
+        self.tokens.assert_token(0, TokenKind::RightBrace)?;
+        let span = self.span_from(start);
+        Ok(Expr::new(ExprKind::Struct(name, variant, fields), span))
+    }
+
+    // `match scrutinee { pattern => expr, ... }`. Arms are separated by
+    // commas or newlines.
+    pub fn parse_match_expr(&mut self) -> ParseResult<Expr> {
+        let start = self.start_pos();
+        self.tokens.assert_token(0, TokenKind::Match)?;
+        let scrutinee = self.parse_nested_expr(true)?;
+        self.skip_whitespace();
+        self.tokens.assert_token(0, TokenKind::LeftBrace)?;
+        let mut arms = Vec::new();
+        loop {
+            self.skip_newlines();
+            if self.tokens.get_token_kind(0) == TokenKind::RightBrace {
+                break;
+            }
+            let pattern = self.parse_pattern()?;
+            self.skip_whitespace();
+            let guard = match self.tokens.get_token_kind(0) {
+                TokenKind::If => {
+                    self.tokens.bump();
+                    Some(self.parse_nested_expr(false)?)
+                }
+                _ => None,
+            };
+            self.skip_whitespace();
+            self.tokens.assert_token(0, TokenKind::FatArrow)?;
+            self.skip_newlines();
+            let body = self.parse_nested_expr(false)?;
+            arms.push(Arm {
+                pattern,
+                guard,
+                body,
+            });
+            self.skip_whitespace();
+            match self.tokens.get_token_kind(0) {
+                TokenKind::Comma | TokenKind::Newline => self.tokens.bump(),
+                TokenKind::RightBrace => break,
+                _ => {
+                    let tkn = self.tokens.get_token(0).clone();
+                    let expected = vec![TokenKind::Comma, TokenKind::RightBrace];
+                    return Err(ParseError::new(expected, tkn));
+                }
+            }
+        }
+        self.tokens.assert_token(0, TokenKind::RightBrace)?;
+        let span = self.span_from(start);
+        Ok(Expr::new(ExprKind::Match(Box::new(scrutinee), arms), span))
+    }
+
+    pub fn parse_pattern(&mut self) -> ParseResult<Pattern> {
+        let start = self.start_pos();
+        let tkn = self.tokens.get_token(0).clone();
+        let kind = match tkn.kind() {
+            TokenKind::Ident if tkn.source_str() == "_" => {
+                self.tokens.bump();
+                PatternKind::Wildcard
+            }
+            TokenKind::Ident => {
+                let name = self.parse_ident()?;
+                let (name, variant) = self.parse_path(name)?;
+                match (self.tokens.get_token_kind(0), variant) {
+                    (TokenKind::LeftBrace, variant) => {
+                        let (fields, rest) = self.parse_field_patterns()?;
+                        PatternKind::Struct(name, variant, fields, rest)
+                    }
+                    (TokenKind::LeftParen, Some(variant)) => {
+                        self.tokens.bump();
+                        let mut patterns = Vec::new();
+                        loop {
+                            self.skip_newlines();
+                            if self.tokens.get_token_kind(0) == TokenKind::RightParen {
+                                break;
+                            }
+                            patterns.push(self.parse_pattern()?);
+                            self.skip_newlines();
+                            match self.tokens.get_token_kind(0) {
+                                TokenKind::Comma => self.tokens.bump(),
+                                _ => break,
+                            }
+                        }
+                        self.tokens.assert_token(0, TokenKind::RightParen)?;
+                        PatternKind::Tuple(name, variant, patterns)
+                    }
+                    (_, Some(variant)) => PatternKind::Path(name, variant),
+                    (_, None) => PatternKind::Binding(name),
+                }
+            }
+            TokenKind::Minus => {
+                self.tokens.bump();
+                let lit = self.parse_literal_pattern()?;
+                let span = self.span_from(start);
+                PatternKind::Literal(Box::new(Expr::new(
+                    ExprKind::UnOp(UnOp::Neg, Box::new(lit)),
+                    span,
+                )))
+            }
+            kind if PATTERN_START.contains(&kind) => {
+                PatternKind::Literal(Box::new(self.parse_literal_pattern()?))
+            }
+            _ => return Err(ParseError::new(PATTERN_START.to_vec(), tkn)),
+        };
+        let span = self.span_from(start);
+        Ok(Pattern { kind, span })
+    }
+
+    fn parse_literal_pattern(&mut self) -> ParseResult<Expr> {
+        self.skip_whitespace();
+        let tkn = self.tokens.get_token(0).clone();
+        let literals = &PATTERN_START[2..];
+        if !literals.contains(&tkn.kind()) {
+            return Err(ParseError::new(literals.to_vec(), tkn));
+        }
+        self.parse_expr(Precedence::Call)
+    }
+
+    // Parses `{ field: pattern, ..., .. }` and returns the fields and
+    // whether the pattern ends with `..`.
+    fn parse_field_patterns(&mut self) -> ParseResult<(Vec<(Ident, Pattern)>, bool)> {
+        self.tokens.assert_token(0, TokenKind::LeftBrace)?;
+        let mut fields = Vec::new();
+        let mut rest = false;
+        loop {
+            self.skip_newlines();
+            match self.tokens.get_token_kind(0) {
+                TokenKind::RightBrace => break,
+                TokenKind::DotDot => {
+                    self.tokens.bump();
+                    rest = true;
+                    self.skip_newlines();
+                    break;
+                }
+                _ => (),
+            }
+            let field_start = self.start_pos();
+            let field = self.parse_ident()?;
+            let pattern = match self.tokens.get_token_kind(0) {
+                TokenKind::Colon => {
+                    self.tokens.bump();
+                    self.parse_pattern()?
+                }
+                _ => Pattern {
+                    kind: PatternKind::Binding(field.clone()),
+                    span: self.span_from(field_start),
+                },
+            };
+            fields.push((field, pattern));
+            self.skip_newlines();
+            match self.tokens.get_token_kind(0) {
+                TokenKind::Comma => self.tokens.bump(),
+                _ => break,
+            }
+        }
+        self.tokens.assert_token(0, TokenKind::RightBrace)?;
+        Ok((fields, rest))
+    }
+
+    pub fn parse_field_expr(&mut self, base: Expr) -> ParseResult<Expr> {
+        let field = self.parse_member()?;
+        let span = self.span_from(base.span.start);
+        Ok(Expr::new(ExprKind::Field(Box::new(base), field), span))
+    }
+
+    // Parses `.field`, or `.0` for an element of a tuple.
+    fn parse_member(&mut self) -> ParseResult<Ident> {
+        if !self.at_tuple_index() {
+            self.tokens.assert_token(0, TokenKind::Period)?;
+            return self.parse_ident();
+        }
+        let tkn = self.tokens.get_token(0).clone();
+        let index: u32 = tkn.source_str()[1..]
+            .parse()
+            .map_err(|_| ParseError::invalid(tkn.clone()))?;
+        self.tokens.bump();
+        self.skip_whitespace();
+        Ok(index.to_string())
+    }
+
+    // `.0` is lexed as a float, which is a tuple index after an expression.
+    fn at_tuple_index(&mut self) -> bool {
+        let tkn = self.tokens.get_token(0);
+        let src = tkn.source_str();
+        tkn.kind() == TokenKind::Float
+            && src.starts_with('.')
+            && src[1..].bytes().all(|b| b.is_ascii_digit())
+    }
+
     pub fn parse_binop_expr(&mut self, prev: Expr) -> ParseResult<Expr> {
         self.skip_whitespace();
         let prefix = self.tokens.get_token(0);
@@ -184,16 +1257,186 @@ impl<'a> Parser<'a> {
             TokenKind::Minus => BinOp::Minus,
             TokenKind::Star => BinOp::Times,
             TokenKind::Slash => BinOp::Divide,
-            _ => return Err(ParseError),
+            TokenKind::Percent => BinOp::Rem,
+            TokenKind::AndAnd => BinOp::And,
+            TokenKind::OrOr => BinOp::Or,
+            TokenKind::Ampersand => BinOp::BitAnd,
+            TokenKind::Bar => BinOp::BitOr,
+            TokenKind::Caret => BinOp::BitXor,
+            TokenKind::LtLt => BinOp::Shl,
+            TokenKind::GtGt => BinOp::Shr,
+            TokenKind::EqEq => BinOp::Eq,
+            TokenKind::BangEq => BinOp::Ne,
+            TokenKind::Lt => BinOp::Lt,
+            TokenKind::LtEq => BinOp::Le,
+            TokenKind::Gt => BinOp::Gt,
+            TokenKind::GtEq => BinOp::Ge,
+            _ => return Err(ParseError::new(BINOPS.to_vec(), prefix.clone())),
         };
         self.tokens.bump();
         self.skip_whitespace();
         let r_expr = self.parse_expr(Precedence::from(&kind))?;
-        Ok(Expr::BinOp(op, Box::new(prev), Box::new(r_expr)))
+        let span = prev.span.start..r_expr.span.end;
+        Ok(Expr::new(
+            ExprKind::BinOp(op, Box::new(prev), Box::new(r_expr)),
+            span,
+        ))
     }
 
-    pub fn exhaust(&mut self) {
-        self.tokens.exhaust()
+    pub fn parse_if_expr(&mut self) -> ParseResult<Expr> {
+        let start = self.start_pos();
+        self.tokens.assert_token(0, TokenKind::If)?;
+        let cond = self.parse_nested_expr(true)?;
+        self.skip_whitespace();
+        let then = self.parse_block_expr()?;
+
+        // `else` may follow on the next line.
+        let snap = self.tokens.save_snap();
+        self.skip_newlines();
+        let els = if self.tokens.get_token_kind(0) == TokenKind::Else {
+            self.tokens.bump();
+            self.skip_whitespace();
+            let els = match self.tokens.get_token_kind(0) {
+                TokenKind::If => self.parse_if_expr()?,
+                _ => self.parse_block_expr()?,
+            };
+            Some(Box::new(els))
+        } else {
+            self.tokens.return_snap(snap);
+            None
+        };
+        let span = self.span_from(start);
+        Ok(Expr::new(
+            ExprKind::If(Box::new(cond), Box::new(then), els),
+            span,
+        ))
+    }
+
+    // Parses a `while`, `loop` or `for` loop with an optional label.
+    pub fn parse_loop_expr(&mut self) -> ParseResult<Expr> {
+        let start = self.start_pos();
+        let label = self.parse_label();
+        if label.is_some() {
+            self.skip_whitespace();
+            self.tokens.assert_token(0, TokenKind::Colon)?;
+            self.skip_whitespace();
+        }
+        let kind = match self.tokens.get_token_kind(0) {
+            TokenKind::While => {
+                self.tokens.bump();
+                let cond = self.parse_nested_expr(true)?;
+                self.skip_whitespace();
+                let body = self.parse_block_expr()?;
+                ExprKind::While(label, Box::new(cond), Box::new(body))
+            }
+            TokenKind::Loop => {
+                self.tokens.bump();
+                self.skip_whitespace();
+                let body = self.parse_block_expr()?;
+                ExprKind::Loop(label, Box::new(body))
+            }
+            TokenKind::For => {
+                self.tokens.bump();
+                self.skip_whitespace();
+                let var = self.parse_ident()?;
+                self.skip_whitespace();
+                self.tokens.assert_token(0, TokenKind::In)?;
+                let lo = self.parse_nested_expr(true)?;
+                self.skip_whitespace();
+                self.tokens.assert_token(0, TokenKind::DotDot)?;
+                let hi = self.parse_nested_expr(true)?;
+                self.skip_whitespace();
+                let body = self.parse_block_expr()?;
+                ExprKind::For(label, var, Box::new(lo), Box::new(hi), Box::new(body))
+            }
+            _ => {
+                let found = self.tokens.get_token(0);
+                let expected = vec![TokenKind::While, TokenKind::Loop, TokenKind::For];
+                return Err(ParseError::new(expected, found.clone()));
+            }
+        };
+        let span = self.span_from(start);
+        Ok(Expr::new(kind, span))
+    }
+
+    // Parses `break` or `continue` with an optional label, or `return`.
+    // `break` and `return` take an optional value.
+    pub fn parse_jump_expr(&mut self) -> ParseResult<Expr> {
+        let start = self.start_pos();
+        let keyword = self.tokens.get_token_kind(0);
+        self.tokens.bump();
+        self.skip_whitespace();
+        let label = match keyword {
+            TokenKind::Return => None,
+            _ => self.parse_label(),
+        };
+        self.skip_whitespace();
+        let value = if keyword != TokenKind::Continue
+            && EXPR_START.contains(&self.tokens.get_token_kind(0))
+        {
+            Some(Box::new(self.parse_expr(Precedence::Lowest)?))
+        } else {
+            None
+        };
+        let kind = match keyword {
+            TokenKind::Break => ExprKind::Break(label, value),
+            TokenKind::Continue => ExprKind::Continue(label),
+            _ => ExprKind::Return(value),
+        };
+        let span = self.span_from(start);
+        Ok(Expr::new(kind, span))
+    }
+
+    fn parse_label(&mut self) -> Option<Label> {
+        let token = self.tokens.get_token(0);
+        if token.kind() != TokenKind::Label {
+            return None;
+        }
+        let label = token.source_str()[1..].to_string();
+        self.tokens.bump();
+        Some(label)
+    }
+
+    pub fn parse_unop_expr(&mut self) -> ParseResult<Expr> {
+        let start = self.start_pos();
+        let op = match self.tokens.get_token_kind(0) {
+            TokenKind::Minus => UnOp::Neg,
+            TokenKind::Bang => UnOp::Not,
+            _ => {
+                let found = self.tokens.get_token(0);
+                let expected = vec![TokenKind::Minus, TokenKind::Bang];
+                return Err(ParseError::new(expected, found.clone()));
+            }
+        };
+        self.tokens.bump();
+        let expr = self.parse_expr(Precedence::Prefix)?;
+        let span = self.span_from(start);
+        Ok(Expr::new(ExprKind::UnOp(op, Box::new(expr)), span))
+    }
+
+    pub fn parse_call_expr(&mut self, callee: Expr) -> ParseResult<Expr> {
+        self.tokens.assert_token(0, TokenKind::LeftParen)?;
+        let mut args = Vec::new();
+        loop {
+            self.skip_newlines();
+            if self.tokens.get_token_kind(0) == TokenKind::RightParen {
+                break;
+            }
+            args.push(self.parse_nested_expr(false)?);
+            self.skip_newlines();
+            match self.tokens.get_token_kind(0) {
+                TokenKind::Comma => self.tokens.bump(),
+                TokenKind::RightParen => break,
+                _ => {
+                    let found = self.tokens.get_token(0);
+                    let expected = vec![TokenKind::Comma, TokenKind::RightParen];
+                    return Err(ParseError::new(expected, found.clone()));
+                }
+            }
+        }
+        self.tokens.bump();
+        let span = callee.span.start..self.tokens.prev_end();
+        Ok(Expr::new(ExprKind::Call(Box::new(callee), args), span))
     }
 }
 
@@ -202,3 +1445,331 @@ impl fmt::Display for Parser<'_> {
         self.tokens.fmt(f)
     }
 }
+
+impl IntTy {
+    fn from_suffix(suffix: &str) -> Option<IntTy> {
+        match suffix {
+            "i8" => Some(IntTy::I8),
+            "i16" => Some(IntTy::I16),
+            "i32" => Some(IntTy::I32),
+            "i64" => Some(IntTy::I64),
+            "u8" => Some(IntTy::U8),
+            "u16" => Some(IntTy::U16),
+            "u32" => Some(IntTy::U32),
+            "u64" => Some(IntTy::U64),
+            _ => None,
+        }
+    }
+}
+
+// Parses an integer literal such as `1_000`, `0xFF`, `0b1010` or `255u8`.
+// Whether it fits its type is checked once the type is inferred.
+fn parse_int(src: &str) -> Option<Literal> {
+    let (radix, digits) = match src.get(..2) {
+        Some("0x") => (16, &src[2..]),
+        Some("0o") => (8, &src[2..]),
+        Some("0b") => (2, &src[2..]),
+        _ => (10, src),
+    };
+    let (digits, typ) = match digits.find(['i', 'u']) {
+        Some(i) => (&digits[..i], Some(IntTy::from_suffix(&digits[i..])?)),
+        None => (digits, None),
+    };
+    let digits = digits.replace('_', "");
+    let val = u64::from_str_radix(&digits, radix).ok()?;
+    Some(Literal::Int(val, typ))
+}
+
+// Parses a float literal such as `1.5`, `.5`, `1e-3` or `1.0f32`.
+fn parse_float(src: &str) -> Option<Literal> {
+    let (digits, typ) = if let Some(digits) = src.strip_suffix("f32") {
+        (digits, Some(FloatTy::F32))
+    } else if let Some(digits) = src.strip_suffix("f64") {
+        (digits, Some(FloatTy::F64))
+    } else {
+        (src, None)
+    };
+    let val = digits.replace('_', "").parse().ok()?;
+    Some(Literal::Float(val, typ))
+}
+
+// Resolves the escape sequences of a string literal body (without quotes).
+// Returns `None` on an unknown or malformed escape.
+fn unescape(src: &str) -> Option<String> {
+    let mut res = String::with_capacity(src.len());
+    let mut chars = src.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            res.push(c);
+            continue;
+        }
+        let escaped = match chars.next()? {
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            '0' => '\0',
+            '"' => '"',
+            '\\' => '\\',
+            'u' => {
+                if chars.next()? != '{' {
+                    return None;
+                }
+                let mut code = String::new();
+                loop {
+                    match chars.next()? {
+                        '}' => break,
+                        c => code.push(c),
+                    }
+                }
+                if code.is_empty() || code.len() > 6 {
+                    return None;
+                }
+                char::from_u32(u32::from_str_radix(&code, 16).ok()?)?
+            }
+            _ => return None,
+        };
+        res.push(escaped);
+    }
+    Some(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use logos::Logos;
+
+    fn parse(src: &str) -> (Vec<Item>, Vec<ParseError>) {
+        Parser::new(TokenKind::lexer(src)).parse_items()
+    }
+
+    fn literal(src: &str) -> ParseResult<Literal> {
+        let expr = Parser::new(TokenKind::lexer(src)).parse_expr(Precedence::Lowest)?;
+        match expr.kind {
+            ExprKind::Literal(lit) => Ok(lit),
+            kind => panic!("expected a literal, got {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn field_doc_comments() {
+        let (items, errors) = parse(
+            "struct Point {\n    /// Horizontal.\n    x: int,\n    /// Vertical,\n    /// downwards.\n    y: int\n}\n",
+        );
+        assert!(errors.is_empty(), "{:?}", errors);
+        let fields = match &items[..] {
+            [Item::Struct(decl)] => &decl.fields,
+            _ => panic!("expected a struct, got {:?}", items),
+        };
+        let docs: Vec<_> = fields.iter().map(|f| f.doc()).collect();
+        assert_eq!(docs, [Some("Horizontal."), Some("Vertical,\ndownwards.")]);
+    }
+
+    #[test]
+    fn field_doc_comment_before_brace() {
+        let (_, errors) = parse("struct Point {\n    x: int\n    /// Nothing here.\n}\n");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].expected_what, Some("field"));
+    }
+
+    #[test]
+    fn variant_doc_comments() {
+        let (items, errors) = parse(
+            "enum Shape {\n    /// A point.\n    Dot,\n    /// Radius.\n    Circle(int),\n    Rect {\n        /// Width.\n        w: int,\n        h: int,\n    },\n}\n",
+        );
+        assert!(errors.is_empty(), "{:?}", errors);
+        let variants = match &items[..] {
+            [Item::Enum(decl)] => &decl.variants,
+            _ => panic!("expected an enum, got {:?}", items),
+        };
+        let docs: Vec<_> = variants.iter().map(|v| v.doc()).collect();
+        assert_eq!(docs, [Some("A point."), Some("Radius."), None]);
+        match &variants[2].fields {
+            VariantFields::Struct(fields) => assert_eq!(fields[0].doc(), Some("Width.")),
+            fields => panic!("expected struct fields, got {:?}", fields),
+        }
+    }
+
+    #[test]
+    fn variant_doc_comment_before_brace() {
+        let (_, errors) = parse("enum Shape {\n    Dot\n    /// Nothing here.\n}\n");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].expected_what, Some("variant"));
+    }
+
+    #[test]
+    fn float_literals() {
+        let cases = [
+            (".5", 0.5, None),
+            ("5.", 5.0, None),
+            ("1_000.5", 1000.5, None),
+            ("2E+3", 2000.0, None),
+            ("1.5e-3f32", 1.5e-3, Some(FloatTy::F32)),
+            ("3f64", 3.0, Some(FloatTy::F64)),
+        ];
+        for (src, val, typ) in cases {
+            match literal(src) {
+                Ok(Literal::Float(v, t)) => assert_eq!((v, t), (val, typ), "{}", src),
+                lit => panic!("expected a float for `{}`, got {:?}", src, lit),
+            }
+        }
+        let err = literal("1e").unwrap_err();
+        assert_eq!(err.to_string(), "invalid float literal `1e`");
+    }
+
+    #[test]
+    fn string_escapes() {
+        let cases = [
+            (r#""a\nb""#, "a\nb"),
+            (r#""\t\r\0""#, "\t\r\0"),
+            (r#""say \"hi\"""#, "say \"hi\""),
+            (r#""C:\\dir""#, "C:\\dir"),
+            (r#""\u{48}\u{e9}\u{1F600}""#, "H\u{e9}\u{1F600}"),
+        ];
+        for (src, val) in cases {
+            match literal(src) {
+                Ok(Literal::String(v)) => assert_eq!(v, val, "{}", src),
+                lit => panic!("expected a string for `{}`, got {:?}", src, lit),
+            }
+        }
+        for src in [
+            r#""\q""#,
+            r#""\u{}""#,
+            r#""\u{1234567}""#,
+            r#""\u{D800}""#,
+            r#""\u41""#,
+        ] {
+            let err = literal(src).unwrap_err();
+            assert!(err.expected.is_empty(), "{}", src);
+            assert_eq!(err.found.source_str(), src);
+        }
+    }
+
+    #[test]
+    fn doc_comments() {
+        let (items, errors) = parse(
+            "/// Answer.\n/// Twice.\nconst X: int = 42\n// Not a doc.\n//// Nor this.\n/// Entry.\nfn main() -> int {\n    /// Local.\n    let y = X\n    y\n}\n",
+        );
+        assert!(errors.is_empty(), "{:?}", errors);
+        let docs: Vec<_> = items.iter().map(|item| item.doc()).collect();
+        assert_eq!(docs, [Some("Answer.\nTwice."), Some("Entry.")]);
+        let stmts = match &items[1] {
+            Item::Fn(decl) => match &decl.body.kind {
+                ExprKind::Block(stmts) => stmts,
+                kind => panic!("expected a block, got {:?}", kind),
+            },
+            item => panic!("expected a function, got {:?}", item),
+        };
+        assert_eq!(stmts[0].doc(), Some("Local."));
+        assert_eq!(stmts[1].doc(), None);
+    }
+
+    #[test]
+    fn stray_doc_comment() {
+        let (_, errors) = parse("fn main() -> int {\n    /// Nothing here.\n    1\n}\n");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].expected_what, Some("`let` or `fn`"));
+    }
+
+    #[test]
+    fn binary_operators() {
+        for op in ["==", "!=", "<=", ">=", "&&", "||", "<<", ">>", "<", ">"] {
+            let src = format!("a {} b", op);
+            let expr = Parser::new(TokenKind::lexer(&src)).parse_expr(Precedence::Lowest);
+            match expr.map(|expr| expr.kind) {
+                Ok(ExprKind::BinOp(binop, _, _)) => assert_eq!(binop.to_string(), op),
+                expr => panic!("expected a binary operation for `{}`, got {:?}", src, expr),
+            }
+        }
+    }
+
+    #[test]
+    fn compound_assignments() {
+        for op in ["+", "-", "*", "/"] {
+            let src = format!("a {}= 2", op);
+            match Parser::new(TokenKind::lexer(&src)).parse_stmt() {
+                Ok(Stmt::Assign(assign)) => {
+                    assert_eq!(assign.op.map(|op| op.to_string()).as_deref(), Some(op))
+                }
+                stmt => panic!("expected an assignment for `{}`, got {:?}", src, stmt),
+            }
+        }
+    }
+
+    #[test]
+    fn split_fat_arrow() {
+        let parse_fn = |src| Parser::new(TokenKind::lexer(src)).parse_fn();
+        assert!(parse_fn("(x: int) => x").is_ok());
+        let err = parse_fn("(x: int) = > x").unwrap_err();
+        assert_eq!(err.expected, [TokenKind::FatArrow]);
+        assert_eq!(err.found.kind(), TokenKind::Eq);
+    }
+
+    #[test]
+    fn int_literals() {
+        let cases = [
+            ("1_000_000", 1_000_000, None),
+            ("0xFF", 255, None),
+            ("0o17", 15, None),
+            ("0b1010_1010", 170, None),
+            ("255u8", 255, Some(IntTy::U8)),
+            ("0x7f_i64", 127, Some(IntTy::I64)),
+            ("18446744073709551615", u64::MAX, None),
+        ];
+        for (src, val, typ) in cases {
+            match literal(src) {
+                Ok(Literal::Int(v, t)) => assert_eq!((v, t), (val, typ), "{}", src),
+                lit => panic!("expected an int for `{}`, got {:?}", src, lit),
+            }
+        }
+        for src in ["0b12", "0x", "0o8", "0xffu7", "18446744073709551616"] {
+            let err = literal(src).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                format!("invalid integer literal `{}`", src)
+            );
+        }
+    }
+
+    #[test]
+    fn error_recovery() {
+        let (items, errors) = parse(
+            "const A int = 1\nfn main() -> int {\n    let = 2\n    let b = 3\n    b + * 4; b\n    if b > 1 { ) } else { 0 }\n}\nconst C: int = 5\n",
+        );
+        let found: Vec<_> = errors.iter().map(|err| err.found.kind()).collect();
+        use TokenKind::*;
+        assert_eq!(found, [Ident, Eq, Star, RightParen], "{:?}", errors);
+        assert!(matches!(items[..], [Item::Fn(_), Item::Const(_)]));
+        let stmts = match &items[0] {
+            Item::Fn(decl) => match &decl.body.kind {
+                ExprKind::Block(stmts) => stmts,
+                kind => panic!("expected a block, got {:?}", kind),
+            },
+            item => panic!("expected a function, got {:?}", item),
+        };
+        let is_error =
+            |stmt: &Stmt| matches!(stmt, Stmt::Expr(expr) if matches!(expr.kind, ExprKind::Error));
+        let errors: Vec<_> = stmts.iter().map(is_error).collect();
+        assert_eq!(errors, [true, false, true, false, false]);
+        match &stmts[4] {
+            Stmt::Expr(expr) => match &expr.kind {
+                ExprKind::If(_, then, _) => match &then.kind {
+                    ExprKind::Block(stmts) => assert!(stmts.len() == 1 && is_error(&stmts[0])),
+                    kind => panic!("expected a block, got {:?}", kind),
+                },
+                kind => panic!("expected an if expression, got {:?}", kind),
+            },
+            stmt => panic!("expected an expression, got {:?}", stmt),
+        }
+    }
+
+    #[test]
+    fn trailing_garbage() {
+        let (items, errors) = parse("fn main() -> int { 0 }\n}\n42\n");
+        assert!(matches!(items[..], [Item::Fn(_)]));
+        let found: Vec<_> = errors.iter().map(|err| err.found.kind()).collect();
+        assert_eq!(found, [TokenKind::RightBrace, TokenKind::Int]);
+        assert!(errors
+            .iter()
+            .all(|err| err.expected.contains(&TokenKind::Fn)));
+    }
+}
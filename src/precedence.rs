@@ -3,15 +3,37 @@ use super::token_kind::TokenKind;
 #[derive(PartialEq, PartialOrd, Debug, Clone)]
 pub enum Precedence {
     Lowest,
-    Add,   // +
-    Times, // *
+    Or,      // ||
+    And,     // &&
+    Compare, // == != < > <= >=
+    BitOr,   // |
+    BitXor,  // ^
+    BitAnd,  // &
+    Shift,   // << >>
+    Add,     // +
+    Times,   // *
+    Prefix,  // -x !x
+    Call,    // f(x) a.b
 }
 
 impl Precedence {
     pub fn from(kind: &TokenKind) -> Precedence {
         match kind {
+            TokenKind::OrOr => Precedence::Or,
+            TokenKind::AndAnd => Precedence::And,
+            TokenKind::EqEq
+            | TokenKind::BangEq
+            | TokenKind::Lt
+            | TokenKind::Gt
+            | TokenKind::LtEq
+            | TokenKind::GtEq => Precedence::Compare,
+            TokenKind::Bar => Precedence::BitOr,
+            TokenKind::Caret => Precedence::BitXor,
+            TokenKind::Ampersand => Precedence::BitAnd,
+            TokenKind::LtLt | TokenKind::GtGt => Precedence::Shift,
             TokenKind::Plus | TokenKind::Minus => Precedence::Add,
-            TokenKind::Star | TokenKind::Slash => Precedence::Times,
+            TokenKind::Star | TokenKind::Slash | TokenKind::Percent => Precedence::Times,
+            TokenKind::LeftParen | TokenKind::Period => Precedence::Call,
             _ => Precedence::Lowest,
         }
     }
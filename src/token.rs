@@ -3,16 +3,28 @@ use std::ops::Range;
 
 use super::token_kind::TokenKind;
 
-#[derive(Clone)]
+pub type Span = Range<usize>;
+
+#[derive(Debug, Clone)]
 pub struct Token {
     _kind: TokenKind,
-    _span: Range<usize>,
+    _span: Span,
     _slice: String,
 }
 
 impl Token {
     pub fn pop_from<'a>(lexer: &mut Lexer<'a, TokenKind>) -> Option<Token> {
         match lexer.next() {
+            Some(TokenKind::Int)
+                if is_decimal(lexer.slice()) && has_trailing_dot(lexer.remainder()) =>
+            {
+                lexer.bump(1);
+                Some(Token {
+                    _kind: TokenKind::Float,
+                    _span: lexer.span(),
+                    _slice: lexer.slice().to_string(),
+                })
+            }
             Some(tkn) => Some(Token {
                 _kind: tkn,
                 _span: lexer.span(),
@@ -29,10 +41,37 @@ impl Token {
         }
     }
 
+    // What is left of the token after its first character, as a token of
+    // `kind`.
+    pub fn split_first(&self, kind: TokenKind) -> Token {
+        Token {
+            _kind: kind,
+            _span: self._span.start + 1..self._span.end,
+            _slice: self._slice[1..].to_string(),
+        }
+    }
+
     pub fn kind(&self) -> TokenKind {
         self._kind
     }
+    pub fn span(&self) -> Span {
+        self._span.clone()
+    }
     pub fn source_str(&self) -> String {
         self._slice.clone()
     }
 }
+
+// A trailing dot turns an integer into a float (`5.`), unless the dot starts
+// a range (`5..`) or a member access (`5.foo`).
+fn has_trailing_dot(rest: &str) -> bool {
+    let mut chars = rest.chars();
+    match chars.next() {
+        Some('.') => !matches!(chars.next(), Some(c) if c == '.' || c == '_' || c.is_alphabetic()),
+        _ => false,
+    }
+}
+
+fn is_decimal(slice: &str) -> bool {
+    slice.bytes().all(|b| b.is_ascii_digit() || b == b'_')
+}
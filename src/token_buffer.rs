@@ -51,7 +51,35 @@ impl<'a> TokenBuffer<'a> {
             self.bump();
             Ok(tkn)
         } else {
-            Err(ParseError)
+            Err(ParseError::new(vec![kind], tkn))
+        }
+    }
+
+    // End of the last consumed token, not counting whitespace and comments.
+    pub fn prev_end(&self) -> usize {
+        for tkn in self.tokens[..self.offset.min(self.tokens.len())]
+            .iter()
+            .rev()
+        {
+            match tkn.kind() {
+                TokenKind::Whitespace
+                | TokenKind::Newline
+                | TokenKind::LineComment
+                | TokenKind::BlockComment
+                | TokenKind::DocComment => (),
+                _ => return tkn.span().end,
+            }
+        }
+        0
+    }
+
+    // Consumes the first character of the next token, leaving the rest of it
+    // as a token of `kind`, as when `>>` closes two lists of type arguments.
+    pub fn split_token(&mut self, kind: TokenKind) {
+        let index = self.offset;
+        self.get_token_global(index);
+        if let Some(tkn) = self.tokens.get_mut(index) {
+            *tkn = tkn.split_first(kind);
         }
     }
 
@@ -66,18 +94,6 @@ impl<'a> TokenBuffer<'a> {
     pub fn return_snap(&mut self, snap: usize) {
         self.offset = snap
     }
-
-    pub fn exhaust(&mut self) {
-        loop {
-            match Token::pop_from(&mut self.lexer) {
-                Some(tkn) => self.tokens.push(tkn),
-                None => {
-                    self.eof = Some(Token::eof(&self.lexer));
-                    break;
-                }
-            }
-        }
-    }
 }
 
 impl fmt::Display for TokenBuffer<'_> {
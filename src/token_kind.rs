@@ -1,4 +1,5 @@
-use logos::Logos;
+use logos::{Lexer, Logos};
+use std::fmt;
 
 #[derive(Logos, Debug, PartialEq, Clone, Copy)]
 pub enum TokenKind {
@@ -10,21 +11,36 @@ pub enum TokenKind {
     If,
     #[token("else")]
     Else,
+    #[token("match")]
+    Match,
     #[token("for")]
     For,
     #[token("while")]
     While,
     #[token("loop")]
     Loop,
+    #[token("in")]
+    In,
+    #[token("break")]
+    Break,
+    #[token("continue")]
+    Continue,
     #[token("return")]
     Return,
     #[token("yield")]
     Yield,
 
+    #[token("true")]
+    True,
+    #[token("false")]
+    False,
+
     #[token("let")]
     Let,
     #[token("const")]
     Const,
+    #[token("mut")]
+    Mut,
 
     #[token("fn")]
     Fn,
@@ -60,11 +76,15 @@ pub enum TokenKind {
     Star,
     #[token("/")]
     Slash,
+    #[token("%")]
+    Percent,
 
     #[token("&")]
     Ampersand,
     #[token("|")]
     Bar,
+    #[token("^")]
+    Caret,
     #[token("!")]
     Bang,
 
@@ -77,6 +97,41 @@ pub enum TokenKind {
     #[token(",")]
     Comma,
 
+    #[token("==")]
+    EqEq,
+    #[token("!=")]
+    BangEq,
+    #[token("<=")]
+    LtEq,
+    #[token(">=")]
+    GtEq,
+    #[token("&&")]
+    AndAnd,
+    #[token("||")]
+    OrOr,
+    #[token("<<")]
+    LtLt,
+    #[token(">>")]
+    GtGt,
+
+    #[token("+=")]
+    PlusEq,
+    #[token("-=")]
+    MinusEq,
+    #[token("*=")]
+    StarEq,
+    #[token("/=")]
+    SlashEq,
+
+    #[token("->")]
+    Arrow,
+    #[token("=>")]
+    FatArrow,
+    #[token("::")]
+    ColonColon,
+    #[token("..")]
+    DotDot,
+
     #[token("(")]
     LeftParen,
     #[token(")")]
@@ -91,16 +146,36 @@ pub enum TokenKind {
     RightBracket,
 
     // Or regular expressions.
-    #[regex("[a-zA-Z]+")]
+    #[regex(r"[\p{XID_Start}_]\p{XID_Continue}*")]
     Ident,
-    #[regex("[0-9]+")]
+    #[regex(r"'[\p{XID_Start}_]\p{XID_Continue}*")]
+    Label,
+    #[regex(r"[0-9][0-9_]*([iu](8|16|32|64))?")]
+    // A radix prefix takes every letter and digit after it, so that a bad
+    // digit makes the literal invalid rather than starting a new token.
+    #[regex(r"0[xob][0-9a-zA-Z_]*")]
     Int,
+    #[regex(r"[0-9][0-9_]*\.[0-9][0-9_]*([eE][+-]?[0-9_]*)?(f32|f64)?")]
+    #[regex(r"\.[0-9][0-9_]*([eE][+-]?[0-9_]*)?(f32|f64)?")]
+    #[regex(r"[0-9][0-9_]*[eE][+-]?[0-9_]*(f32|f64)?")]
+    #[regex(r"[0-9][0-9_]*(f32|f64)")]
+    Float,
+    #[regex(r#""([^"\\]|\\.)*""#)]
+    String,
 
     #[token("\n")]
     Newline,
     #[regex(r"[ \t\f]+")]
     Whitespace,
 
+    // Comments
+    #[regex(r"//[^\n]*")]
+    LineComment,
+    #[token("/*", block_comment)]
+    BlockComment,
+    #[regex(r"///([^/\n][^\n]*)?", priority = 10)]
+    DocComment,
+
     // Logos requires one token variant to handle errors,
     // it can be named anything you wish.
     #[error]
@@ -108,3 +183,183 @@ pub enum TokenKind {
 
     Eof,
 }
+
+// Consumes the rest of a (possibly nested) block comment. Fails if the
+// comment is never closed.
+fn block_comment(lex: &mut Lexer<TokenKind>) -> bool {
+    let rest = lex.remainder().as_bytes();
+    let mut depth = 1;
+    let mut i = 0;
+    while i < rest.len() {
+        match &rest[i..(i + 2).min(rest.len())] {
+            b"/*" => {
+                depth += 1;
+                i += 2;
+            }
+            b"*/" => {
+                depth -= 1;
+                i += 2;
+                if depth == 0 {
+                    lex.bump(i);
+                    return true;
+                }
+            }
+            _ => i += 1,
+        }
+    }
+    lex.bump(rest.len());
+    false
+}
+
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let desc = match self {
+            TokenKind::Use => "`use`",
+            TokenKind::If => "`if`",
+            TokenKind::Else => "`else`",
+            TokenKind::Match => "`match`",
+            TokenKind::For => "`for`",
+            TokenKind::While => "`while`",
+            TokenKind::Loop => "`loop`",
+            TokenKind::In => "`in`",
+            TokenKind::Break => "`break`",
+            TokenKind::Continue => "`continue`",
+            TokenKind::Return => "`return`",
+            TokenKind::Yield => "`yield`",
+            TokenKind::True => "`true`",
+            TokenKind::False => "`false`",
+            TokenKind::Let => "`let`",
+            TokenKind::Const => "`const`",
+            TokenKind::Mut => "`mut`",
+            TokenKind::Fn => "`fn`",
+            TokenKind::Shard => "`shard`",
+            TokenKind::Struct => "`struct`",
+            TokenKind::Enum => "`enum`",
+            TokenKind::Type => "`type`",
+            TokenKind::Impl => "`impl`",
+            TokenKind::Local => "`local`",
+            TokenKind::Extern => "`extern`",
+            TokenKind::Eq => "`=`",
+            TokenKind::Lt => "`<`",
+            TokenKind::Gt => "`>`",
+            TokenKind::Plus => "`+`",
+            TokenKind::Minus => "`-`",
+            TokenKind::Star => "`*`",
+            TokenKind::Slash => "`/`",
+            TokenKind::Percent => "`%`",
+            TokenKind::Ampersand => "`&`",
+            TokenKind::Bar => "`|`",
+            TokenKind::Caret => "`^`",
+            TokenKind::Bang => "`!`",
+            TokenKind::Period => "`.`",
+            TokenKind::Colon => "`:`",
+            TokenKind::Semicolon => "`;`",
+            TokenKind::Comma => "`,`",
+            TokenKind::EqEq => "`==`",
+            TokenKind::BangEq => "`!=`",
+            TokenKind::LtEq => "`<=`",
+            TokenKind::GtEq => "`>=`",
+            TokenKind::AndAnd => "`&&`",
+            TokenKind::OrOr => "`||`",
+            TokenKind::LtLt => "`<<`",
+            TokenKind::GtGt => "`>>`",
+            TokenKind::PlusEq => "`+=`",
+            TokenKind::MinusEq => "`-=`",
+            TokenKind::StarEq => "`*=`",
+            TokenKind::SlashEq => "`/=`",
+            TokenKind::Arrow => "`->`",
+            TokenKind::FatArrow => "`=>`",
+            TokenKind::ColonColon => "`::`",
+            TokenKind::DotDot => "`..`",
+            TokenKind::LeftParen => "`(`",
+            TokenKind::RightParen => "`)`",
+            TokenKind::LeftBrace => "`{`",
+            TokenKind::RightBrace => "`}`",
+            TokenKind::LeftBracket => "`[`",
+            TokenKind::RightBracket => "`]`",
+            TokenKind::Ident => "identifier",
+            TokenKind::Label => "label",
+            TokenKind::Int => "integer literal",
+            TokenKind::Float => "float literal",
+            TokenKind::String => "string literal",
+            TokenKind::Newline => "newline",
+            TokenKind::Whitespace => "whitespace",
+            TokenKind::LineComment | TokenKind::BlockComment => "comment",
+            TokenKind::DocComment => "doc comment",
+            TokenKind::Error => "invalid token",
+            TokenKind::Eof => "end of file",
+        };
+        f.write_str(desc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::token::Token;
+
+    // The kinds of the tokens of `src`, without whitespace.
+    fn kinds(src: &str) -> Vec<TokenKind> {
+        let mut lexer = TokenKind::lexer(src);
+        let mut kinds = Vec::new();
+        while let Some(tkn) = Token::pop_from(&mut lexer) {
+            if tkn.kind() != TokenKind::Whitespace {
+                kinds.push(tkn.kind());
+            }
+        }
+        kinds
+    }
+
+    #[test]
+    fn floats() {
+        for src in ".5 5. 1.5 1.5e-3 1.5e-3f32 2E+10 1_000.5 3f64 1e".split(' ') {
+            assert_eq!(kinds(src), [TokenKind::Float], "{}", src);
+        }
+        use TokenKind::*;
+        assert_eq!(kinds("0..5"), [Int, DotDot, Int]);
+        assert_eq!(kinds("5.x"), [Int, Period, Ident]);
+        assert_eq!(kinds("1.5."), [Float, Period]);
+    }
+
+    #[test]
+    fn comments() {
+        use TokenKind::*;
+        assert_eq!(kinds("/* a /* b */ c */ x"), [BlockComment, Ident]);
+        assert_eq!(
+            kinds("/**/ /* */ */"),
+            [BlockComment, BlockComment, Star, Slash]
+        );
+        assert_eq!(kinds("/* a /* b */"), [Error]);
+        assert_eq!(kinds("/// doc"), [DocComment]);
+        assert_eq!(kinds("///"), [DocComment]);
+        assert_eq!(kinds("// line"), [LineComment]);
+        assert_eq!(kinds("//// rule"), [LineComment]);
+        assert_eq!(kinds("x // y\ny"), [Ident, LineComment, Newline, Ident]);
+    }
+
+    #[test]
+    fn operators() {
+        use TokenKind::*;
+        let ops = "== != <= >= && || << >> += -= *= /= -> => :: ..";
+        let expected = [
+            EqEq, BangEq, LtEq, GtEq, AndAnd, OrOr, LtLt, GtGt, PlusEq, MinusEq, StarEq, SlashEq,
+            Arrow, FatArrow, ColonColon, DotDot,
+        ];
+        assert_eq!(kinds(ops), expected);
+        assert_eq!(
+            kinds("= > < = & & : :"),
+            [Eq, Gt, Lt, Eq, Ampersand, Ampersand, Colon, Colon]
+        );
+    }
+
+    #[test]
+    fn identifiers_and_ints() {
+        for src in "x my_var x1 _ _tmp ñandú 変数".split(' ') {
+            assert_eq!(kinds(src), [TokenKind::Ident], "{}", src);
+        }
+        for src in "1_000_000 0xFF 0o17 0b1010_1010 255u8 7i64 0b12 0x".split(' ') {
+            assert_eq!(kinds(src), [TokenKind::Int], "{}", src);
+        }
+        assert_eq!(kinds("1x"), [TokenKind::Int, TokenKind::Ident]);
+    }
+}
@@ -1,77 +1,1954 @@
 use super::ast;
+use super::const_eval::Value;
+use super::types::{EnumType, StructType, Type};
 use super::wasm;
+use std::collections::HashMap;
+use std::rc::Rc;
 
 pub enum AST {}
 
 #[derive(Clone, Copy)]
-pub struct Context {}
+enum Binding {
+    // Index of the first of `n` consecutive locals or globals.
+    Local(wasm::LocalIdx, usize),
+    Global(wasm::GlobalIdx, usize),
+    // A name bound directly to a function literal or item needs no storage.
+    Func(wasm::FuncIdx),
+}
+
+// A label that `br` can target. Frames are kept for every enclosing block,
+// loop and if, so that the index of a label is its distance from the top.
+#[derive(Clone)]
+enum Frame {
+    Other,
+    Break(Option<ast::Label>),
+    Continue(Option<ast::Label>),
+}
+
+// The parts of the wasm module being built, which every function adds to.
+// They are threaded through translation by reference, unlike the `Context`
+// of the code being translated.
+#[derive(Default)]
+pub struct Builder {
+    // Contents of the data segments, in the order they are laid out in memory.
+    datas: Vec<Vec<u8>>,
+    globals: Vec<wasm::Global>,
+    // Interned function signatures, as (params, results).
+    types: Vec<(Vec<wasm::ValType>, Vec<wasm::ValType>)>,
+    // Number of imported functions, which come first in the function index
+    // space.
+    imports: usize,
+    // Function items and functions lifted out of lambdas. The function
+    // index of `funcs[i]` is `imports + i`.
+    funcs: Vec<wasm::Func>,
+    // Functions used as values, by table slot.
+    table: Vec<wasm::FuncIdx>,
+    // The allocator function that struct values are placed in linear memory
+    // with, and the global holding the next free address. Added on first use.
+    heap: Option<(wasm::FuncIdx, wasm::GlobalIdx)>,
+}
+
+impl Builder {
+    fn data_offset(&self, index: usize) -> usize {
+        self.datas[..index].iter().map(|d| d.len()).sum()
+    }
+
+    // Places `bytes` in a data segment, reusing an identical one if possible.
+    // Returns the memory address of the bytes.
+    fn add_data(&mut self, bytes: &[u8]) -> usize {
+        let index = match self.datas.iter().position(|d| d.as_slice() == bytes) {
+            Some(index) => index,
+            None => {
+                self.datas.push(bytes.to_vec());
+                self.datas.len() - 1
+            }
+        };
+        self.data_offset(index)
+    }
+
+    fn add_type(
+        &mut self,
+        params: Vec<wasm::ValType>,
+        results: Vec<wasm::ValType>,
+    ) -> wasm::TypeIdx {
+        let sig = (params, results);
+        let index = match self.types.iter().position(|t| *t == sig) {
+            Some(index) => index,
+            None => {
+                self.types.push(sig);
+                self.types.len() - 1
+            }
+        };
+        index as wasm::TypeIdx
+    }
+
+    fn add_global(
+        &mut self,
+        typ: wasm::ValType,
+        mutability: wasm::Mut,
+        init: wasm::Instr,
+    ) -> wasm::GlobalIdx {
+        self.globals.push(wasm::Global {
+            typ: wasm::GlobalType { mutability, typ },
+            init: wasm::Expr::from(init),
+        });
+        (self.globals.len() - 1) as wasm::GlobalIdx
+    }
+
+    // Reserves a function index for a function that `define_fn` lowers
+    // later, so that it can be referred to before it is defined.
+    fn reserve_fn(&mut self) -> wasm::FuncIdx {
+        self.funcs.push(wasm::Func {
+            typ: 0,
+            locals: Vec::new(),
+            body: wasm::Expr::new(),
+        });
+        (self.imports + self.funcs.len() - 1) as wasm::FuncIdx
+    }
+
+    // Returns the index of the allocator function, adding it if needed. It
+    // takes a size in bytes and returns the address of that many free bytes.
+    fn alloc_fn(&mut self) -> wasm::FuncIdx {
+        if let Some((func, _)) = self.heap {
+            return func;
+        }
+        let func = self.reserve_fn();
+        // The initial address is only known once all data is laid out.
+        let i32 = wasm::ValType::NumType(wasm::NumType::I32);
+        let global = self.add_global(i32, wasm::Mut::Var, wasm::Instr::I32Const(0));
+        self.heap = Some((func, global));
+        func
+    }
+
+    // Returns the table slot of function `func`, adding it if needed.
+    fn table_slot(&mut self, func: wasm::FuncIdx) -> usize {
+        match self.table.iter().position(|f| *f == func) {
+            Some(slot) => slot,
+            None => {
+                self.table.push(func);
+                self.table.len() - 1
+            }
+        }
+    }
+}
+
+// A binding made in a function, after those in `prev`.
+struct Bound {
+    name: ast::Ident,
+    binding: Binding,
+    prev: Option<Rc<Bound>>,
+}
+
+// The bindings and labels in scope at the code being translated. Each step
+// of the translation returns a new context, so the bindings are shared
+// between contexts rather than copied.
+#[derive(Clone, Default)]
+pub struct Context {
+    // The program-level bindings. Those that need storage are globals.
+    items: Rc<HashMap<ast::Ident, Binding>>,
+    // The bindings made in functions, newest first, and the newest binding
+    // outside of each open scope.
+    bindings: Option<Rc<Bound>>,
+    scopes: Vec<Option<Rc<Bound>>>,
+    // Locals of the function being translated, parameters included.
+    locals: Vec<wasm::ValType>,
+    // Labels of the function being translated, innermost last.
+    frames: Vec<Frame>,
+}
+
+impl Context {
+    pub fn new() -> Context {
+        Context::default()
+    }
+
+    fn push_scope(&self) -> Context {
+        let mut ctx = self.clone();
+        ctx.scopes.push(ctx.bindings.clone());
+        ctx
+    }
+
+    fn pop_scope(&self) -> Context {
+        let mut ctx = self.clone();
+        ctx.bindings = ctx.scopes.pop().expect("no scope to pop");
+        ctx
+    }
+
+    fn bind(&self, name: &str, binding: Binding) -> Context {
+        let mut ctx = self.clone();
+        if ctx.scopes.is_empty() {
+            Rc::make_mut(&mut ctx.items).insert(name.to_string(), binding);
+        } else {
+            ctx.bindings = Some(Rc::new(Bound {
+                name: name.to_string(),
+                binding,
+                prev: ctx.bindings.take(),
+            }));
+        }
+        ctx
+    }
+
+    fn push_frame(&self, frame: Frame) -> Context {
+        let mut ctx = self.clone();
+        ctx.frames.push(frame);
+        ctx
+    }
+
+    fn pop_frame(&self) -> Context {
+        let mut ctx = self.clone();
+        ctx.frames.pop();
+        ctx
+    }
+
+    // The label index a `break` or `continue` to the loop labeled `label`,
+    // or to the innermost loop, jumps to.
+    fn branch_depth(&self, is_break: bool, label: &Option<ast::Label>) -> wasm::LabelIdx {
+        let index = self
+            .frames
+            .iter()
+            .rposition(|frame| match (frame, is_break) {
+                (Frame::Break(l), true) | (Frame::Continue(l), false) => {
+                    label.is_none() || l == label
+                }
+                _ => false,
+            })
+            .expect("unresolved loop after type checking");
+        (self.frames.len() - 1 - index) as wasm::LabelIdx
+    }
+
+    fn lookup(&self, name: &str) -> Binding {
+        let mut bindings = std::iter::successors(self.bindings.as_deref(), |b| b.prev.as_deref());
+        let found = match bindings.find(|bound| bound.name == name) {
+            Some(bound) => Some(&bound.binding),
+            None => self.items.get(name),
+        };
+        *found.expect("unresolved name after type checking")
+    }
+
+    // Allocates storage for a new binding of type `typ`: globals in the
+    // program scope, locals everywhere else.
+    fn declare(&self, b: &mut Builder, name: &str, typ: &Type) -> (Context, Binding) {
+        let mut ctx = self.clone();
+        let valtypes = typ.valtypes();
+        let binding = if ctx.scopes.is_empty() {
+            let index = b.globals.len() as wasm::GlobalIdx;
+            for valtype in &valtypes {
+                b.add_global(*valtype, wasm::Mut::Var, zero(valtype));
+            }
+            Binding::Global(index, valtypes.len())
+        } else {
+            let index = ctx.locals.len() as wasm::LocalIdx;
+            ctx.locals.extend(&valtypes);
+            Binding::Local(index, valtypes.len())
+        };
+        (ctx.bind(name, binding), binding)
+    }
+
+    // Binds `name` to immutable globals holding the constant `value`.
+    fn declare_const(&self, b: &mut Builder, name: &str, value: &Value) -> Context {
+        let index = b.globals.len() as wasm::GlobalIdx;
+        let instrs = value_to_wasm(b, value);
+        let n = instrs.len();
+        for (valtype, instr) in instrs {
+            b.add_global(valtype, wasm::Mut::Const, instr);
+        }
+        self.bind(name, Binding::Global(index, n))
+    }
+
+    // Allocates an unnamed local of the current function.
+    fn scratch_local(&self, valtype: wasm::ValType) -> (Context, wasm::LocalIdx) {
+        let mut ctx = self.clone();
+        ctx.locals.push(valtype);
+        (ctx, self.locals.len() as wasm::LocalIdx)
+    }
+
+    // Allocates unnamed locals for a value of type `typ`.
+    fn scratch_locals(&self, typ: &Type) -> (Context, Binding) {
+        let mut ctx = self.clone();
+        let valtypes = typ.valtypes();
+        ctx.locals.extend(&valtypes);
+        let index = self.locals.len() as wasm::LocalIdx;
+        (ctx, Binding::Local(index, valtypes.len()))
+    }
+}
+
+const PAGE_SIZE: usize = 65536;
+
+// Alignment of every allocation, enough for any field.
+const HEAP_ALIGN: usize = 8;
 
 trait WASMTranslator<T, U> {
-    fn to_wasm(ctx: &Context, node: &T) -> (Context, U);
+    fn to_wasm(ctx: &Context, b: &mut Builder, node: &T) -> (Context, U);
 }
 
-impl WASMTranslator<ast::Program, wasm::Module> for AST {
-    fn to_wasm(ctx: &Context, node: &ast::Program) -> (Context, wasm::Module) {
-        let (_, mainfunc) = AST::to_wasm(ctx, &node.stmt);
+// Translates the main wasm module of `program`, unless it is made of shards
+// alone, and the wasm module of every shard, named after it.
+pub fn translate(program: &ast::Program) -> Vec<(Option<ast::Ident>, wasm::Module)> {
+    let has_main = program.items.iter().any(|item| match item {
+        ast::Item::Fn(decl) => decl.name == "main",
+        _ => false,
+    });
+    let mut units = Vec::new();
+    if has_main {
+        units.push(None);
     }
+    units.extend(
+        program
+            .modules
+            .iter()
+            .filter_map(|m| m.shard.clone().map(Some)),
+    );
+    units
+        .into_iter()
+        .map(|unit| {
+            let module = unit_to_wasm(program, unit.as_ref());
+            (unit, module)
+        })
+        .collect()
 }
 
-impl WASMTranslator<ast::Expr, wasm::Expr> for AST {
-    fn to_wasm(ctx: &Context, node: &ast::Expr) -> (Context, wasm::Expr) {
-        match node {
-            ast::Expr::Block(stmts) => {
-                let mut expr = wasm::Expr::new();
-                for stmt in stmts {
-                    let (_, mut subexpr) = AST::to_wasm(ctx, stmt);
-                    expr.append(&mut subexpr);
+fn typ_of(expr: &ast::Expr) -> &Type {
+    expr.typ.as_ref().expect("expression was not type checked")
+}
+
+fn fn_signature(typ: &Type) -> (Vec<wasm::ValType>, Vec<wasm::ValType>) {
+    match typ {
+        Type::Fn(params, ret) => (
+            params.iter().flat_map(|p| p.valtypes()).collect(),
+            ret.valtypes(),
+        ),
+        _ => panic!("expected a function type after type checking"),
+    }
+}
+
+// Lowers the function of type `typ` with arguments `args` and body `body`
+// into the function reserved at `index`. Type checking guarantees the body
+// refers to no locals of the enclosing function.
+fn define_fn(
+    ctx: &Context,
+    b: &mut Builder,
+    index: wasm::FuncIdx,
+    args: &[ast::Arg],
+    typ: &Type,
+    body: &ast::Expr,
+) {
+    let params = match typ {
+        Type::Fn(params, _) => params,
+        _ => panic!("expected a function type after type checking"),
+    };
+
+    let mut fctx = ctx.push_scope();
+    fctx.locals = Vec::new();
+    fctx.frames = Vec::new();
+    for (arg, typ) in args.iter().zip(params) {
+        fctx = fctx.declare(b, &arg.name, typ).0;
+    }
+    let nparams = fctx.locals.len();
+    let (fctx, body) = AST::to_wasm(&fctx, b, body);
+
+    let (params, results) = fn_signature(typ);
+    let typ = b.add_type(params, results);
+    b.funcs[index as usize - b.imports] = wasm::Func {
+        typ,
+        locals: fctx.locals[nparams..].to_vec(),
+        body,
+    };
+}
+
+// Lifts the function literal `expr` into a function of its own and returns
+// its function index.
+fn lift_fn(ctx: &Context, b: &mut Builder, expr: &ast::Expr) -> wasm::FuncIdx {
+    let (args, body) = match &expr.kind {
+        ast::ExprKind::Fn(args, _, body) => (args, body),
+        _ => panic!("expected a function literal"),
+    };
+    let index = b.reserve_fn();
+    define_fn(ctx, b, index, args, typ_of(expr), body);
+    index
+}
+
+// Code after an expression of type `!` is unreachable, which lets the stack
+// match whatever the surrounding code expects.
+fn end_never(typ: &Type, expr: &mut wasm::Expr) {
+    if *typ == Type::Never {
+        expr.instrs.push(wasm::Instr::Unreachable);
+    }
+}
+
+fn block_type(b: &mut Builder, result: Vec<wasm::ValType>) -> wasm::BlockType {
+    match result.len() {
+        0 => wasm::BlockType::None,
+        1 => wasm::BlockType::ValType(result[0]),
+        _ => wasm::BlockType::TypeIdx(b.add_type(Vec::new(), result)),
+    }
+}
+
+// Lowers a branch of an `if`, dropping its value if the `if` has none.
+fn branch_to_wasm(
+    ctx: &Context,
+    b: &mut Builder,
+    branch: &ast::Expr,
+    result: &[wasm::ValType],
+) -> (Context, wasm::Expr) {
+    let (ctx, mut expr) = AST::to_wasm(ctx, b, branch);
+    if result.is_empty() {
+        for _ in typ_of(branch).valtypes() {
+            expr.instrs.push(wasm::Instr::Drop);
+        }
+    }
+    (ctx, expr)
+}
+
+fn func_ref(b: &mut Builder, func: wasm::FuncIdx) -> wasm::Expr {
+    let slot = b.table_slot(func);
+    wasm::Expr::from(wasm::Instr::I32Const(slot as wasm::U32))
+}
+
+fn args_to_wasm(ctx: &Context, b: &mut Builder, args: &[ast::Expr]) -> (Context, wasm::Expr) {
+    let mut ctx = ctx.clone();
+    let mut expr = wasm::Expr::new();
+    for arg in args {
+        let (subctx, mut subexpr) = AST::to_wasm(&ctx, b, arg);
+        ctx = subctx;
+        expr.append(&mut subexpr);
+    }
+    (ctx, expr)
+}
+
+fn zero(valtype: &wasm::ValType) -> wasm::Instr {
+    match valtype {
+        wasm::ValType::NumType(wasm::NumType::I32) => wasm::Instr::I32Const(0),
+        wasm::ValType::NumType(wasm::NumType::I64) => wasm::Instr::I64Const(0),
+        wasm::ValType::NumType(wasm::NumType::F32) => wasm::Instr::F32Const(0.0),
+        wasm::ValType::NumType(wasm::NumType::F64) => wasm::Instr::F64Const(0.0),
+        wasm::ValType::VecType(_) => unreachable!("no type lowers to a vector"),
+        wasm::ValType::RefType(t) => wasm::Instr::RefNull(*t),
+    }
+}
+
+// The instructions pushing the wasm values of a constant, with their types.
+fn value_to_wasm(b: &mut Builder, value: &Value) -> Vec<(wasm::ValType, wasm::Instr)> {
+    let num = wasm::ValType::NumType;
+    match value {
+        Value::Int(i, ast::IntTy::I64 | ast::IntTy::U64) => {
+            vec![(
+                num(wasm::NumType::I64),
+                wasm::Instr::I64Const(*i as wasm::U64),
+            )]
+        }
+        Value::Int(i, _) => vec![(
+            num(wasm::NumType::I32),
+            wasm::Instr::I32Const(*i as wasm::U32),
+        )],
+        Value::Float(z, ast::FloatTy::F32) => {
+            vec![(
+                num(wasm::NumType::F32),
+                wasm::Instr::F32Const(*z as wasm::F32),
+            )]
+        }
+        Value::Float(z, ast::FloatTy::F64) => {
+            vec![(num(wasm::NumType::F64), wasm::Instr::F64Const(*z))]
+        }
+        Value::Bool(b) => vec![(
+            num(wasm::NumType::I32),
+            wasm::Instr::I32Const(*b as wasm::U32),
+        )],
+        Value::String(s) => {
+            let ptr = b.add_data(s.as_bytes());
+            vec![
+                (
+                    num(wasm::NumType::I32),
+                    wasm::Instr::I32Const(ptr as wasm::U32),
+                ),
+                (
+                    num(wasm::NumType::I32),
+                    wasm::Instr::I32Const(s.len() as wasm::U32),
+                ),
+            ]
+        }
+        Value::Unit => Vec::new(),
+    }
+}
+
+// Lowers a statement list. The value of the final expression statement is
+// left on the stack if `keep_last`, those of the others are dropped.
+fn stmts_to_wasm(
+    ctx: &Context,
+    b: &mut Builder,
+    stmts: &[ast::Stmt],
+    keep_last: bool,
+) -> (Context, wasm::Expr) {
+    let mut ctx = ctx.clone();
+    // Function items can be called before they are defined.
+    let mut fn_indices = Vec::new();
+    for stmt in stmts {
+        if let ast::Stmt::Fn(decl) = stmt {
+            let index = b.reserve_fn();
+            ctx = ctx.bind(&decl.name, Binding::Func(index));
+            fn_indices.push(index);
+        }
+    }
+    let mut fn_indices = fn_indices.into_iter();
+    let mut expr = wasm::Expr::new();
+    for (i, stmt) in stmts.iter().enumerate() {
+        let (subctx, mut subexpr) = match stmt {
+            ast::Stmt::Fn(decl) => {
+                let typ = decl
+                    .typ
+                    .as_ref()
+                    .expect("function item was not type checked");
+                let index = fn_indices.next().unwrap();
+                define_fn(&ctx, b, index, &decl.args, typ, &decl.body);
+                (ctx.clone(), wasm::Expr::new())
+            }
+            stmt => AST::to_wasm(&ctx, b, stmt),
+        };
+        ctx = subctx;
+        expr.append(&mut subexpr);
+        if let ast::Stmt::Expr(e) = stmt {
+            if !keep_last || i + 1 < stmts.len() {
+                for _ in typ_of(e).valtypes() {
+                    expr.instrs.push(wasm::Instr::Drop);
                 }
-                (ctx.clone(), expr)
             }
-            ast::Expr::BinOp(op, lexpr, rexpr) => {
+        }
+    }
+    (ctx, expr)
+}
+
+// Translates the modules of the shard `unit`, or those outside of shards if
+// it is `None`, into a wasm module.
+fn unit_to_wasm(node: &ast::Program, unit: Option<&ast::Ident>) -> wasm::Module {
+    let modules: Vec<&ast::Module> = node
+        .modules
+        .iter()
+        .filter(|module| module.shard.as_ref() == unit)
+        .collect();
+
+    // Imported functions come first in the function index space.
+    let mut ctx = Context::new();
+    let mut b = Builder::default();
+    // The external functions of the unit, then those of other modules it
+    // refers to.
+    let mut externs: Vec<&ast::ExternFn> = Vec::new();
+    for module in &modules {
+        for item in &node.items[module.items.clone()] {
+            if let ast::Item::Extern(ext) = item {
+                externs.push(ext);
+            }
+        }
+    }
+    for name in modules.iter().flat_map(|module| &module.externs) {
+        if let ast::Item::Extern(ext) = find_item(node, name) {
+            if !externs.iter().any(|e| e.name == ext.name) {
+                externs.push(ext);
+            }
+        }
+    }
+    let mut imports = Vec::new();
+    for ext in externs {
+        let typ = ext
+            .typ
+            .as_ref()
+            .expect("extern function was not type checked");
+        let (params, results) = fn_signature(typ);
+        let typ = b.add_type(params, results);
+        let index = imports.len() as wasm::FuncIdx;
+        ctx = ctx.bind(&ext.name, Binding::Func(index));
+        imports.push(wasm::Import {
+            module: String::from("env"),
+            // The host knows the function by its name without the path of
+            // the module that declares it.
+            name: ext.name.rsplit("::").next().unwrap().to_string(),
+            desc: wasm::ImportDesc::Func(typ),
+        });
+    }
+    // Then the functions of other shards, from their wasm modules.
+    let mut imported: Vec<&ast::Ident> = Vec::new();
+    for name in modules.iter().flat_map(|module| &module.imports) {
+        if imported.contains(&name) {
+            continue;
+        }
+        imported.push(name);
+        let (module, decl) = find_fn(node, name);
+        let typ = decl
+            .typ
+            .as_ref()
+            .expect("function item was not type checked");
+        let (params, results) = fn_signature(typ);
+        let typ = b.add_type(params, results);
+        let index = imports.len() as wasm::FuncIdx;
+        ctx = ctx.bind(name, Binding::Func(index));
+        imports.push(wasm::Import {
+            module: module.shard.clone().unwrap(),
+            name: module.unqualify(name).to_string(),
+            desc: wasm::ImportDesc::Func(typ),
+        });
+    }
+    b.imports = imports.len();
+
+    // The constants of the unit, then those of other modules it refers to.
+    let mut consts: Vec<&ast::ConstDecl> = Vec::new();
+    for module in &modules {
+        for item in &node.items[module.items.clone()] {
+            if let ast::Item::Const(decl) = item {
+                consts.push(decl);
+            }
+        }
+    }
+    for name in modules.iter().flat_map(|module| &module.consts) {
+        if let ast::Item::Const(decl) = find_item(node, name) {
+            if !consts.iter().any(|c| c.name == decl.name) {
+                consts.push(decl);
+            }
+        }
+    }
+    for decl in consts {
+        let value = decl.value.as_ref().expect("constant was not evaluated");
+        ctx = ctx.declare_const(&mut b, &decl.name, value);
+    }
+
+    // The functions of `impl` blocks are named by their paths.
+    let mut fns = Vec::new();
+    let items = modules
+        .iter()
+        .flat_map(|module| &node.items[module.items.clone()]);
+    for item in items {
+        match item {
+            ast::Item::Fn(decl) => fns.push((decl.name.clone(), decl)),
+            ast::Item::Impl(block) => {
+                fns.extend(block.fns.iter().map(|decl| (block.path(decl), decl)))
+            }
+            _ => (),
+        }
+    }
+    let mut fn_indices = Vec::new();
+    for (name, _) in &fns {
+        let index = b.reserve_fn();
+        ctx = ctx.bind(name, Binding::Func(index));
+        fn_indices.push(index);
+    }
+    for ((_, decl), index) in fns.into_iter().zip(fn_indices) {
+        let typ = decl
+            .typ
+            .as_ref()
+            .expect("function item was not type checked");
+        define_fn(&ctx, &mut b, index, &decl.args, typ, &decl.body);
+    }
+
+    let mut datas = Vec::new();
+    for (index, init) in b.datas.iter().enumerate() {
+        let offset = b.data_offset(index) as wasm::U32;
+        datas.push(wasm::Data {
+            init: init.clone(),
+            mode: wasm::DataMode::Active {
+                memory: 0,
+                offset: wasm::Expr::from(wasm::Instr::I32Const(offset)),
+            },
+        });
+    }
+    let data_end = b.data_offset(b.datas.len());
+    let pages = data_end.div_ceil(PAGE_SIZE);
+
+    // The heap starts after the data.
+    if let Some((func, global)) = b.heap {
+        let i32 = wasm::ValType::NumType(wasm::NumType::I32);
+        let typ = b.add_type(vec![i32], vec![i32]);
+        let start = data_end.next_multiple_of(HEAP_ALIGN) as wasm::U32;
+        b.globals[global as usize].init = wasm::Expr::from(wasm::Instr::I32Const(start));
+        b.funcs[func as usize - b.imports] = wasm::Func {
+            typ,
+            locals: vec![i32],
+            body: wasm::Expr::from(alloc_body(global)),
+        };
+    }
+
+    let exports = unit_exports(&ctx, &modules, &node.items);
+    let types = b
+        .types
+        .iter()
+        .map(|(params, results)| wasm::FuncType {
+            from: wasm::ResultType {
+                values: params.clone(),
+            },
+            to: wasm::ResultType {
+                values: results.clone(),
+            },
+        })
+        .collect();
+
+    let mut tables = Vec::new();
+    let mut elems = Vec::new();
+    if !b.table.is_empty() {
+        let size = b.table.len() as wasm::U32;
+        tables.push(wasm::Table {
+            typ: wasm::TableType {
+                lim: wasm::Limits {
+                    min: size,
+                    max: Some(size),
+                },
+                et: wasm::RefType::FuncRef,
+            },
+        });
+        elems.push(wasm::Elem {
+            typ: wasm::RefType::FuncRef,
+            init: b
+                .table
+                .iter()
+                .map(|f| wasm::Expr::from(wasm::Instr::RefFunc(*f)))
+                .collect(),
+            mode: wasm::ElemMode::Active {
+                table: 0,
+                offset: wasm::Expr::from(wasm::Instr::I32Const(0)),
+            },
+        });
+    }
+
+    wasm::Module {
+        types,
+        funcs: b.funcs,
+        tables,
+        mems: vec![wasm::Mem {
+            typ: wasm::MemType {
+                lim: wasm::Limits {
+                    min: pages.max(1) as wasm::U32,
+                    max: None,
+                },
+            },
+        }],
+        globals: b.globals,
+        elems,
+        datas,
+        start: None,
+        imports,
+        exports,
+    }
+}
+
+// The function item linked as `name`, and the module that declares it.
+fn find_fn<'a>(program: &'a ast::Program, name: &str) -> (&'a ast::Module, &'a ast::FnDecl) {
+    for module in &program.modules {
+        for item in &program.items[module.items.clone()] {
+            let found = match item {
+                ast::Item::Fn(decl) if decl.name == name => Some(decl),
+                ast::Item::Impl(block) => block.fns.iter().find(|decl| block.path(decl) == name),
+                _ => None,
+            };
+            if let Some(decl) = found {
+                return (module, decl);
+            }
+        }
+    }
+    panic!("unresolved function `{}` after type checking", name)
+}
+
+// The constant or external function linked as `name`.
+fn find_item<'a>(program: &'a ast::Program, name: &str) -> &'a ast::Item {
+    let path = name.rsplit_once("::").map_or("", |(path, _)| path);
+    let module = program.modules.iter().find(|module| module.path == path);
+    let items = module.map_or(&[][..], |module| &program.items[module.items.clone()]);
+    let found = items.iter().find(|item| match item {
+        ast::Item::Const(decl) => decl.name == name,
+        ast::Item::Extern(ext) => ext.name == name,
+        _ => false,
+    });
+    found.unwrap_or_else(|| panic!("unresolved item `{}` after type checking", name))
+}
+
+// The exports of the wasm module of `modules`: `main` for the main module,
+// and the functions and constants of a shard that are not local, by their
+// names in it. A string constant `NAME` is exported as `NAME.ptr` and
+// `NAME.len`. Both export their memory.
+fn unit_exports(ctx: &Context, modules: &[&ast::Module], items: &[ast::Item]) -> Vec<wasm::Export> {
+    let mut exports = Vec::new();
+    for module in modules {
+        for item in &items[module.items.clone()] {
+            let names = match item {
+                ast::Item::Fn(decl) if decl.local => continue,
+                ast::Item::Fn(decl) if module.shard.is_some() || decl.name == "main" => {
+                    vec![decl.name.clone()]
+                }
+                ast::Item::Impl(block) if module.shard.is_some() => block
+                    .fns
+                    .iter()
+                    .filter(|decl| !decl.local)
+                    .map(|decl| block.path(decl))
+                    .collect(),
+                ast::Item::Const(decl) if module.shard.is_some() && !decl.local => {
+                    vec![decl.name.clone()]
+                }
+                _ => continue,
+            };
+            for name in names {
+                let short = module.unqualify(&name);
+                let descs = match ctx.lookup(&name) {
+                    Binding::Func(index) => {
+                        vec![(short.to_string(), wasm::ExportDesc::Func(index))]
+                    }
+                    Binding::Global(index, 1) => {
+                        vec![(short.to_string(), wasm::ExportDesc::Global(index))]
+                    }
+                    // A string is held in two globals: the address of its
+                    // bytes in the exported memory, and its length.
+                    Binding::Global(index, 2) => vec![
+                        (format!("{}.ptr", short), wasm::ExportDesc::Global(index)),
+                        (
+                            format!("{}.len", short),
+                            wasm::ExportDesc::Global(index + 1),
+                        ),
+                    ],
+                    // A unit constant has no value to export.
+                    _ => continue,
+                };
+                for (name, desc) in descs {
+                    exports.push(wasm::Export { name, desc });
+                }
+            }
+        }
+    }
+    exports.push(wasm::Export {
+        name: String::from("memory"),
+        desc: wasm::ExportDesc::Mem(0),
+    });
+    exports
+}
+
+impl WASMTranslator<ast::Expr, wasm::Expr> for AST {
+    fn to_wasm(ctx: &Context, b: &mut Builder, node: &ast::Expr) -> (Context, wasm::Expr) {
+        match &node.kind {
+            ast::ExprKind::Block(stmts) => {
+                // A block whose value is unused has type `()`.
+                let keep_last = *typ_of(node) != Type::Unit;
+                let (ctx, expr) = stmts_to_wasm(&ctx.push_scope(), b, stmts, keep_last);
+                (ctx.pop_scope(), expr)
+            }
+            ast::ExprKind::If(cond, then, els) => {
+                let result = typ_of(node).valtypes();
+                let (ctx, mut expr) = AST::to_wasm(ctx, b, &**cond);
+                let ctx = ctx.push_frame(Frame::Other);
+                let (ctx, then) = branch_to_wasm(&ctx, b, then, &result);
+                let (ctx, els) = match els {
+                    Some(els) => branch_to_wasm(&ctx, b, els, &result),
+                    None => (ctx, wasm::Expr::new()),
+                };
+                let ctx = ctx.pop_frame();
+                let bt = block_type(b, result);
+                expr.instrs
+                    .push(wasm::Instr::If(bt, then.instrs, els.instrs));
+                end_never(typ_of(node), &mut expr);
+                (ctx, expr)
+            }
+            // Loops are a `loop` nested in a `block`: branching to the loop
+            // continues, branching to the block breaks.
+            ast::ExprKind::Loop(label, body) => {
+                let result = typ_of(node).valtypes();
+                let ctx = ctx
+                    .push_frame(Frame::Break(label.clone()))
+                    .push_frame(Frame::Continue(label.clone()));
+                let (ctx, mut body) = AST::to_wasm(&ctx, b, &**body);
+                body.instrs.push(wasm::Instr::Br(0));
+                let ctx = ctx.pop_frame().pop_frame();
+                let bt = block_type(b, result);
+                let instrs = vec![
+                    wasm::Instr::Loop(wasm::BlockType::None, body.instrs),
+                    wasm::Instr::Unreachable,
+                ];
+                let mut expr = wasm::Expr::from(wasm::Instr::Block(bt, instrs));
+                end_never(typ_of(node), &mut expr);
+                (ctx, expr)
+            }
+            ast::ExprKind::While(label, cond, body) => {
+                // The condition is inside the loop, but jumps in it do not
+                // target this loop.
+                let ctx = ctx.push_frame(Frame::Other).push_frame(Frame::Other);
+                let (ctx, mut instrs) = AST::to_wasm(&ctx, b, &**cond);
+                instrs.instrs.push(wasm::Instr::I32Eqz);
+                instrs.instrs.push(wasm::Instr::BrIf(1));
+                let ctx = ctx
+                    .pop_frame()
+                    .pop_frame()
+                    .push_frame(Frame::Break(label.clone()))
+                    .push_frame(Frame::Continue(label.clone()));
+                let (ctx, mut body) = AST::to_wasm(&ctx, b, &**body);
+                instrs.append(&mut body);
+                instrs.instrs.push(wasm::Instr::Br(0));
+                let inner = wasm::Instr::Loop(wasm::BlockType::None, instrs.instrs);
+                let expr = wasm::Expr::from(wasm::Instr::Block(wasm::BlockType::None, vec![inner]));
+                (ctx.pop_frame().pop_frame(), expr)
+            }
+            // The body is wrapped in one more block, so that `continue`
+            // still increments the counter.
+            ast::ExprKind::For(label, var, lo, hi, body) => {
+                let t = match typ_of(lo) {
+                    Type::Int(t) => *t,
+                    typ => panic!("range over `{}` after type checking", typ),
+                };
+                let valtype = Type::Int(t).valtypes()[0];
+                let one = match valtype {
+                    wasm::ValType::NumType(wasm::NumType::I64) => wasm::Instr::I64Const(1),
+                    _ => wasm::Instr::I32Const(1),
+                };
+
+                let (ctx, mut expr) = AST::to_wasm(ctx, b, &**lo);
+                let (ctx, mut hiwasm) = AST::to_wasm(&ctx, b, &**hi);
+                let (ctx, end) = ctx.scratch_local(valtype);
+                let (ctx, binding) = ctx.push_scope().declare(b, var, &Type::Int(t));
+                let counter = match binding {
+                    Binding::Local(index, _) => index,
+                    _ => unreachable!("loop variables are locals"),
+                };
+                expr.instrs.push(wasm::Instr::LocalSet(counter));
+                expr.append(&mut hiwasm);
+                expr.instrs.push(wasm::Instr::LocalSet(end));
+
+                let ctx = ctx
+                    .push_frame(Frame::Break(label.clone()))
+                    .push_frame(Frame::Other)
+                    .push_frame(Frame::Continue(label.clone()));
+                let (ctx, body) = AST::to_wasm(&ctx, b, &**body);
+                let ctx = ctx.pop_frame().pop_frame().pop_frame().pop_scope();
+
+                let mut instrs = vec![wasm::Instr::LocalGet(counter), wasm::Instr::LocalGet(end)];
+                instrs.append(&mut int_binop(ast::BinOp::Ge, t));
+                instrs.push(wasm::Instr::BrIf(1));
+                instrs.push(wasm::Instr::Block(wasm::BlockType::None, body.instrs));
+                instrs.push(wasm::Instr::LocalGet(counter));
+                instrs.push(one);
+                instrs.append(&mut int_binop(ast::BinOp::Add, t));
+                instrs.push(wasm::Instr::LocalSet(counter));
+                instrs.push(wasm::Instr::Br(0));
+                let inner = wasm::Instr::Loop(wasm::BlockType::None, instrs);
+                expr.instrs
+                    .push(wasm::Instr::Block(wasm::BlockType::None, vec![inner]));
+                (ctx, expr)
+            }
+            ast::ExprKind::Break(label, value) => {
+                let (ctx, mut expr) = match value {
+                    Some(value) => AST::to_wasm(ctx, b, &**value),
+                    None => (ctx.clone(), wasm::Expr::new()),
+                };
+                let depth = ctx.branch_depth(true, label);
+                expr.instrs.push(wasm::Instr::Br(depth));
+                (ctx, expr)
+            }
+            ast::ExprKind::Return(value) => {
+                let (ctx, mut expr) = match value {
+                    Some(value) => AST::to_wasm(ctx, b, &**value),
+                    None => (ctx.clone(), wasm::Expr::new()),
+                };
+                expr.instrs.push(wasm::Instr::Return);
+                (ctx, expr)
+            }
+            ast::ExprKind::Continue(label) => {
+                let depth = ctx.branch_depth(false, label);
+                (ctx.clone(), wasm::Expr::from(wasm::Instr::Br(depth)))
+            }
+            // `&&` and `||` only evaluate their right operand if needed.
+            ast::ExprKind::BinOp(op @ (ast::BinOp::And | ast::BinOp::Or), lexpr, rexpr) => {
+                let (ctx, mut expr) = AST::to_wasm(ctx, b, &**lexpr);
+                let (ctx, rwasm) = AST::to_wasm(&ctx.push_frame(Frame::Other), b, &**rexpr);
+                let ctx = ctx.pop_frame();
+                let bt = wasm::BlockType::ValType(wasm::ValType::NumType(wasm::NumType::I32));
+                let instr = match op {
+                    ast::BinOp::And => {
+                        wasm::Instr::If(bt, rwasm.instrs, vec![wasm::Instr::I32Const(0)])
+                    }
+                    _ => wasm::Instr::If(bt, vec![wasm::Instr::I32Const(1)], rwasm.instrs),
+                };
+                expr.instrs.push(instr);
+                (ctx, expr)
+            }
+            ast::ExprKind::BinOp(op, lexpr, rexpr) => {
                 let mut expr = wasm::Expr::new();
-                let (_, mut lwasm) = AST::to_wasm(ctx, &**lexpr);
-                let (_, mut rwasm) = AST::to_wasm(ctx, &**rexpr);
-                let (_, mut bowasm) = AST::to_wasm(ctx, op);
+                let (ctx, mut lwasm) = AST::to_wasm(ctx, b, &**lexpr);
+                let (ctx, mut rwasm) = AST::to_wasm(&ctx, b, &**rexpr);
+                let (ctx, mut bowasm) = AST::to_wasm(&ctx, b, &(*op, typ_of(lexpr).clone()));
                 expr.append(&mut lwasm);
                 expr.append(&mut rwasm);
                 expr.append(&mut bowasm);
-                (ctx.clone(), expr)
+                (ctx, expr)
+            }
+            ast::ExprKind::UnOp(op, operand) => {
+                let typ = typ_of(operand);
+                let mut expr = wasm::Expr::new();
+                // Integers are negated by subtracting them from zero.
+                if let (ast::UnOp::Neg, Type::Int(_)) = (op, typ) {
+                    expr.instrs.push(zero(&typ.valtypes()[0]));
+                }
+                let (ctx, mut operand) = AST::to_wasm(ctx, b, &**operand);
+                let (ctx, mut unwasm) = AST::to_wasm(&ctx, b, &(*op, typ.clone()));
+                expr.append(&mut operand);
+                expr.append(&mut unwasm);
+                (ctx, expr)
+            }
+            ast::ExprKind::Literal(l) => AST::to_wasm(ctx, b, l),
+            ast::ExprKind::Var(name) => match ctx.lookup(name) {
+                Binding::Func(index) => (ctx.clone(), func_ref(b, index)),
+                binding => (ctx.clone(), wasm::Expr::from(load(binding))),
+            },
+            ast::ExprKind::Fn(..) => {
+                let index = lift_fn(ctx, b, node);
+                (ctx.clone(), func_ref(b, index))
+            }
+            // Calls to a known function are direct, other function values
+            // are called through the table.
+            ast::ExprKind::Call(callee, args) => {
+                // Tuple variants are called like functions.
+                if let ast::ExprKind::Path(_, variant) = &callee.kind {
+                    let fields = args.iter().enumerate().map(|(i, arg)| (i.to_string(), arg));
+                    let fields: Vec<(String, &ast::Expr)> = fields.collect();
+                    let fields = fields.iter().map(|(i, arg)| (i.as_str(), *arg));
+                    return new_value(ctx, b, typ_of(node), Some(variant), fields.collect());
+                }
+                let known = match &callee.kind {
+                    ast::ExprKind::Var(name) => match ctx.lookup(name) {
+                        Binding::Func(index) => Some((ctx.clone(), index)),
+                        _ => None,
+                    },
+                    ast::ExprKind::Fn(..) => Some((ctx.clone(), lift_fn(ctx, b, callee))),
+                    _ => None,
+                };
+                if let Some((ctx, index)) = known {
+                    let (ctx, mut expr) = args_to_wasm(&ctx, b, args);
+                    expr.instrs.push(wasm::Instr::Call(index));
+                    end_never(typ_of(node), &mut expr);
+                    return (ctx, expr);
+                }
+
+                // The table slot goes on top of the arguments, but the callee
+                // is evaluated first. Stash it unless it is a plain variable.
+                let (ctx, mut expr, mut load) = match &callee.kind {
+                    ast::ExprKind::Var(_) => {
+                        let (ctx, load) = AST::to_wasm(ctx, b, &**callee);
+                        (ctx, wasm::Expr::new(), load)
+                    }
+                    _ => {
+                        let (ctx, mut expr) = AST::to_wasm(ctx, b, &**callee);
+                        let i32 = wasm::ValType::NumType(wasm::NumType::I32);
+                        let (ctx, tmp) = ctx.scratch_local(i32);
+                        expr.instrs.push(wasm::Instr::LocalSet(tmp));
+                        (ctx, expr, wasm::Expr::from(wasm::Instr::LocalGet(tmp)))
+                    }
+                };
+                let (ctx, mut argexpr) = args_to_wasm(&ctx, b, args);
+                let (params, results) = fn_signature(typ_of(callee));
+                let typ = b.add_type(params, results);
+                expr.append(&mut argexpr);
+                expr.append(&mut load);
+                expr.instrs.push(wasm::Instr::CallIndirect(0, typ));
+                end_never(typ_of(node), &mut expr);
+                (ctx, expr)
+            }
+            // The fields are stored in source order into fresh memory.
+            ast::ExprKind::Struct(_, variant, fields) => {
+                let fields = fields.iter().map(|(name, value)| (name.as_str(), value));
+                new_value(ctx, b, typ_of(node), variant.as_deref(), fields.collect())
+            }
+            ast::ExprKind::Tuple(exprs) => {
+                let fields = exprs
+                    .iter()
+                    .enumerate()
+                    .map(|(i, expr)| (i.to_string(), expr));
+                let fields: Vec<(String, &ast::Expr)> = fields.collect();
+                let fields = fields.iter().map(|(i, expr)| (i.as_str(), *expr));
+                new_value(ctx, b, typ_of(node), None, fields.collect())
+            }
+            ast::ExprKind::Path(_, variant) => {
+                new_value(ctx, b, typ_of(node), Some(variant), Vec::new())
+            }
+            ast::ExprKind::Match(scrutinee, arms) => match_to_wasm(ctx, b, node, scrutinee, arms),
+            ast::ExprKind::Field(..) => {
+                let (ctx, mut expr, offset) = place_to_wasm(ctx, b, node);
+                let (ctx, mut load) = load_from(&ctx, typ_of(node), offset);
+                expr.instrs.append(&mut load);
+                (ctx, expr)
+            }
+            ast::ExprKind::Error => (ctx.clone(), wasm::Expr::new()),
+        }
+    }
+}
+
+// Allocates a struct, or an enum value of the variant `variant`, and stores
+// its tag and `fields` in source order. Returns the address.
+fn new_value(
+    ctx: &Context,
+    b: &mut Builder,
+    typ: &Type,
+    variant: Option<&str>,
+    fields: Vec<(&str, &ast::Expr)>,
+) -> (Context, wasm::Expr) {
+    let (tag, base, payload): (_, _, &StructType) = match (typ, variant) {
+        (Type::Struct(st) | Type::Tuple(st), None) => (None, 0, st),
+        (Type::Enum(en), Some(variant)) => {
+            let (tag, found) = en
+                .variant(variant)
+                .expect("unknown variant after type checking");
+            (Some(tag), en.payload_offset(), &found.payload)
+        }
+        (typ, _) => panic!("value of type `{}` built after type checking", typ),
+    };
+    let alloc = b.alloc_fn();
+    let i32 = wasm::ValType::NumType(wasm::NumType::I32);
+    let (mut ctx, ptr) = ctx.scratch_local(i32);
+    let mut expr = wasm::Expr::from(vec![
+        wasm::Instr::I32Const(typ.size()),
+        wasm::Instr::Call(alloc),
+        wasm::Instr::LocalSet(ptr),
+    ]);
+    if let Some(tag) = tag {
+        expr.instrs.push(wasm::Instr::LocalGet(ptr));
+        expr.instrs.push(wasm::Instr::I32Const(tag));
+        expr.instrs.push(wasm::Instr::I32Store(mem_arg(
+            0,
+            &Type::Int(ast::IntTy::U32),
+        )));
+    }
+    for (name, value) in fields {
+        let (offset, typ) = payload
+            .field(name)
+            .expect("unknown field after type checking");
+        expr.instrs.push(wasm::Instr::LocalGet(ptr));
+        let (subctx, mut value) = AST::to_wasm(&ctx, b, value);
+        expr.append(&mut value);
+        let (subctx, mut store) = store_to(&subctx, typ, base + offset);
+        expr.instrs.append(&mut store);
+        ctx = subctx;
+    }
+    expr.instrs.push(wasm::Instr::LocalGet(ptr));
+    (ctx, expr)
+}
+
+// A `match` keeps the scrutinee in locals and tries the arms in order, each
+// in a block that a failed test breaks out of. On an enum it first
+// dispatches on the tag with `br_table`, to the arms that may match the
+// variant:
+//
+//     block $done
+//       block $v1
+//         block $v0
+//           (br_table $v0 $v1 (tag))
+//         end
+//         (arms for variant 0)
+//       end
+//       (arms for variant 1)
+//     end
+//
+// Exhaustiveness makes the end of the arms unreachable.
+fn match_to_wasm(
+    ctx: &Context,
+    b: &mut Builder,
+    node: &ast::Expr,
+    scrutinee: &ast::Expr,
+    arms: &[ast::Arm],
+) -> (Context, wasm::Expr) {
+    let typ = typ_of(scrutinee);
+    let result = typ_of(node).valtypes();
+    let (ctx, mut expr) = AST::to_wasm(ctx, b, scrutinee);
+    let (ctx, value) = ctx.scratch_locals(typ);
+    expr.instrs.append(&mut store(value));
+
+    let mut ctx = ctx.push_frame(Frame::Other);
+    let done = ctx.frames.len() - 1;
+    let body = match typ {
+        Type::Enum(en) if !en.variants.is_empty() => {
+            let n = en.variants.len();
+            let mut instrs = load(value);
+            instrs.push(wasm::Instr::I32Load(mem_arg(
+                0,
+                &Type::Int(ast::IntTy::U32),
+            )));
+            let labels = (0..n as wasm::LabelIdx).collect();
+            instrs.push(wasm::Instr::BrTable(labels, n as wasm::LabelIdx - 1));
+            for _ in 1..n {
+                ctx = ctx.push_frame(Frame::Other);
+            }
+            for i in 0..n {
+                let arms = arms
+                    .iter()
+                    .filter(|arm| pattern_variant(&arm.pattern, en).is_none_or(|v| v == i));
+                let (subctx, mut code) =
+                    arms_to_wasm(&ctx, b, arms, (typ, value), true, &result, done);
+                instrs = vec![wasm::Instr::Block(wasm::BlockType::None, instrs)];
+                instrs.append(&mut code);
+                ctx = if i + 1 < n {
+                    subctx.pop_frame()
+                } else {
+                    subctx
+                };
+            }
+            instrs
+        }
+        _ => {
+            let (subctx, code) =
+                arms_to_wasm(&ctx, b, arms.iter(), (typ, value), false, &result, done);
+            ctx = subctx;
+            code
+        }
+    };
+    let ctx = ctx.pop_frame();
+    let bt = block_type(b, result);
+    expr.instrs.push(wasm::Instr::Block(bt, body));
+    end_never(typ_of(node), &mut expr);
+    (ctx, expr)
+}
+
+// The variant a pattern on an enum matches, if it is limited to one.
+fn pattern_variant(pattern: &ast::Pattern, en: &EnumType) -> Option<usize> {
+    match &pattern.kind {
+        ast::PatternKind::Path(_, variant)
+        | ast::PatternKind::Tuple(_, variant, _)
+        | ast::PatternKind::Struct(_, Some(variant), _, _) => {
+            en.variant(variant).map(|(tag, _)| tag as usize)
+        }
+        _ => None,
+    }
+}
+
+// Lowers the arms tried on the scrutinee, a value of type `typ` stored in
+// `value`, ending in `unreachable`. If `known_tag`, the tag of the value has
+// been checked.
+fn arms_to_wasm<'a>(
+    ctx: &Context,
+    b: &mut Builder,
+    arms: impl Iterator<Item = &'a ast::Arm>,
+    (typ, value): (&Type, Binding),
+    known_tag: bool,
+    result: &[wasm::ValType],
+    done: usize,
+) -> (Context, Vec<wasm::Instr>) {
+    let mut ctx = ctx.clone();
+    let mut instrs = Vec::new();
+    for arm in arms {
+        let arm_ctx = ctx.push_scope().push_frame(Frame::Other);
+        let (arm_ctx, mut code) = pattern_to_wasm(&arm_ctx, b, &arm.pattern, typ, value, known_tag);
+        let arm_ctx = match &arm.guard {
+            Some(guard) => {
+                let (arm_ctx, mut guard) = AST::to_wasm(&arm_ctx, b, guard);
+                code.append(&mut guard.instrs);
+                code.push(wasm::Instr::I32Eqz);
+                code.push(wasm::Instr::BrIf(0));
+                arm_ctx
             }
-            ast::Expr::Literal(l) => AST::to_wasm(ctx, l),
-            _ => (ctx.clone(), wasm::Expr::new()),
+            None => arm_ctx,
+        };
+        let (arm_ctx, mut body) = branch_to_wasm(&arm_ctx, b, &arm.body, result);
+        code.append(&mut body.instrs);
+        code.push(wasm::Instr::Br(
+            (arm_ctx.frames.len() - 1 - done) as wasm::LabelIdx,
+        ));
+        instrs.push(wasm::Instr::Block(wasm::BlockType::None, code));
+        ctx = arm_ctx.pop_frame().pop_scope();
+    }
+    instrs.push(wasm::Instr::Unreachable);
+    (ctx, instrs)
+}
+
+// Lowers the tests of `pattern` against the value of type `typ` stored in
+// `value`, which break out of the innermost block on failure, and binds the
+// names the pattern introduces.
+fn pattern_to_wasm(
+    ctx: &Context,
+    b: &mut Builder,
+    pattern: &ast::Pattern,
+    typ: &Type,
+    value: Binding,
+    known_tag: bool,
+) -> (Context, Vec<wasm::Instr>) {
+    let typ = typ.unalias();
+    let variant = match &pattern.kind {
+        ast::PatternKind::Wildcard => return (ctx.clone(), Vec::new()),
+        ast::PatternKind::Binding(name) => return (ctx.bind(name, value), Vec::new()),
+        ast::PatternKind::Literal(lit) => {
+            let mut instrs = load(value);
+            let (ctx, mut lit) = AST::to_wasm(ctx, b, &**lit);
+            instrs.append(&mut lit.instrs);
+            let (ctx, mut ne) = AST::to_wasm(&ctx, b, &(ast::BinOp::Ne, typ.clone()));
+            instrs.append(&mut ne.instrs);
+            instrs.push(wasm::Instr::BrIf(0));
+            return (ctx, instrs);
+        }
+        ast::PatternKind::Struct(_, None, fields, _) => {
+            let st = match typ {
+                Type::Struct(st) => st,
+                _ => panic!("struct pattern on `{}` after type checking", typ),
+            };
+            let fields = fields.iter().map(|(name, p)| (name.clone(), p));
+            return fields_to_wasm(ctx, b, value, 0, st, fields.collect());
         }
+        ast::PatternKind::Path(_, variant)
+        | ast::PatternKind::Tuple(_, variant, _)
+        | ast::PatternKind::Struct(_, Some(variant), _, _) => variant,
+    };
+    let en = match typ {
+        Type::Enum(en) => en,
+        _ => panic!("variant pattern on `{}` after type checking", typ),
+    };
+    let (tag, found) = en
+        .variant(variant)
+        .expect("unknown variant after type checking");
+    let mut instrs = Vec::new();
+    if !known_tag {
+        instrs.append(&mut load(value));
+        instrs.push(wasm::Instr::I32Load(mem_arg(
+            0,
+            &Type::Int(ast::IntTy::U32),
+        )));
+        instrs.push(wasm::Instr::I32Const(tag));
+        instrs.push(wasm::Instr::I32Ne);
+        instrs.push(wasm::Instr::BrIf(0));
     }
+    let fields = match &pattern.kind {
+        ast::PatternKind::Tuple(_, _, patterns) => patterns
+            .iter()
+            .enumerate()
+            .map(|(i, p)| (i.to_string(), p))
+            .collect(),
+        ast::PatternKind::Struct(_, _, fields, _) => {
+            fields.iter().map(|(name, p)| (name.clone(), p)).collect()
+        }
+        _ => Vec::new(),
+    };
+    let (ctx, mut tests) =
+        fields_to_wasm(ctx, b, value, en.payload_offset(), &found.payload, fields);
+    instrs.append(&mut tests);
+    (ctx, instrs)
+}
+
+// Loads the fields that `fields` match into locals and tests them. The
+// fields of `payload` start at `base` past the address in `value`.
+fn fields_to_wasm(
+    ctx: &Context,
+    b: &mut Builder,
+    value: Binding,
+    base: wasm::U32,
+    payload: &StructType,
+    fields: Vec<(String, &ast::Pattern)>,
+) -> (Context, Vec<wasm::Instr>) {
+    let mut ctx = ctx.clone();
+    let mut instrs = Vec::new();
+    for (name, pattern) in fields {
+        if let ast::PatternKind::Wildcard = pattern.kind {
+            continue;
+        }
+        let (offset, typ) = payload
+            .field(&name)
+            .expect("unknown field after type checking");
+        let (subctx, field) = ctx.scratch_locals(typ);
+        instrs.append(&mut load(value));
+        let (subctx, mut load) = load_from(&subctx, typ, base + offset);
+        instrs.append(&mut load);
+        instrs.append(&mut store(field));
+        let (subctx, mut tests) = pattern_to_wasm(&subctx, b, pattern, typ, field, false);
+        instrs.append(&mut tests);
+        ctx = subctx;
+    }
+    (ctx, instrs)
+}
+
+// Lowers a field access chain like `p.pos.x` to the address of the struct
+// it starts from, and the offset of the accessed field within it. Other
+// expressions are at offset 0 of their own value.
+fn place_to_wasm(
+    ctx: &Context,
+    b: &mut Builder,
+    expr: &ast::Expr,
+) -> (Context, wasm::Expr, wasm::U32) {
+    match &expr.kind {
+        ast::ExprKind::Field(base, name) => {
+            let (ctx, wasm, offset) = place_to_wasm(ctx, b, base);
+            let field = match typ_of(base) {
+                Type::Struct(st) | Type::Tuple(st) => st.field(name).map(|(offset, _)| offset),
+                _ => None,
+            };
+            let field = field.expect("unknown field after type checking");
+            (ctx, wasm, offset + field)
+        }
+        _ => {
+            let (ctx, wasm) = AST::to_wasm(ctx, b, expr);
+            (ctx, wasm, 0)
+        }
+    }
+}
+
+// Struct, enum and tuple values are addresses, so every `let` and assignment
+// stores a copy of the value unless it is freshly built. Even an immutable
+// binding needs one: it may be bound to a value that a mutable binding still
+// refers to.
+fn copy_if_shared(ctx: &Context, b: &mut Builder, expr: &ast::Expr) -> (Context, Vec<wasm::Instr>) {
+    let typ = typ_of(expr);
+    let fresh = match &expr.kind {
+        ast::ExprKind::Struct(..) | ast::ExprKind::Tuple(_) | ast::ExprKind::Path(..) => true,
+        ast::ExprKind::Call(callee, _) => matches!(callee.kind, ast::ExprKind::Path(..)),
+        _ => false,
+    };
+    if fresh || !matches!(typ, Type::Struct(_) | Type::Enum(_) | Type::Tuple(_)) {
+        return (ctx.clone(), Vec::new());
+    }
+    let alloc = b.alloc_fn();
+    let i32 = wasm::ValType::NumType(wasm::NumType::I32);
+    let (ctx, src) = ctx.scratch_local(i32);
+    let (ctx, dst) = ctx.scratch_local(i32);
+    let instrs = vec![
+        wasm::Instr::LocalSet(src),
+        wasm::Instr::I32Const(typ.size()),
+        wasm::Instr::Call(alloc),
+        wasm::Instr::LocalTee(dst),
+        wasm::Instr::LocalGet(src),
+        wasm::Instr::I32Const(typ.size()),
+        wasm::Instr::MemoryCopy,
+        wasm::Instr::LocalGet(dst),
+    ];
+    (ctx, instrs)
+}
+
+fn mem_arg(offset: wasm::U32, typ: &Type) -> wasm::MemArg {
+    wasm::MemArg {
+        offset,
+        align: typ.align().trailing_zeros(),
+    }
+}
+
+// Loads a value of type `typ` from `offset` past the address on the stack.
+// A nested struct is not loaded: its value is its address.
+fn load_from(ctx: &Context, typ: &Type, offset: wasm::U32) -> (Context, Vec<wasm::Instr>) {
+    use wasm::Instr::*;
+    let m = mem_arg(offset, typ);
+    let instrs = match typ {
+        Type::Int(ast::IntTy::I8) => vec![I32Load8S(m)],
+        Type::Int(ast::IntTy::U8) | Type::Bool => vec![I32Load8U(m)],
+        Type::Int(ast::IntTy::I16) => vec![I32Load16S(m)],
+        Type::Int(ast::IntTy::U16) => vec![I32Load16U(m)],
+        Type::Int(ast::IntTy::I32 | ast::IntTy::U32) | Type::Fn(_, _) => vec![I32Load(m)],
+        Type::Int(ast::IntTy::I64 | ast::IntTy::U64) => vec![I64Load(m)],
+        Type::Float(ast::FloatTy::F32) => vec![F32Load(m)],
+        Type::Float(ast::FloatTy::F64) => vec![F64Load(m)],
+        Type::String => {
+            let (ctx, addr) = ctx.scratch_local(wasm::ValType::NumType(wasm::NumType::I32));
+            let len = mem_arg(offset + 4, typ);
+            let instrs = vec![LocalTee(addr), I32Load(m), LocalGet(addr), I32Load(len)];
+            return (ctx, instrs);
+        }
+        Type::Struct(_) | Type::Enum(_) | Type::Tuple(_) if offset == 0 => Vec::new(),
+        Type::Struct(_) | Type::Enum(_) | Type::Tuple(_) => vec![I32Const(offset), I32Add],
+        Type::Alias(alias) => return load_from(ctx, &alias.typ, offset),
+        Type::Unit | Type::Never | Type::Error => vec![Drop],
+    };
+    (ctx.clone(), instrs)
+}
+
+// Stores the value on top of the stack at `offset` past the address below
+// it. A nested struct is copied in.
+fn store_to(ctx: &Context, typ: &Type, offset: wasm::U32) -> (Context, Vec<wasm::Instr>) {
+    use wasm::Instr::*;
+    let i32 = wasm::ValType::NumType(wasm::NumType::I32);
+    let m = mem_arg(offset, typ);
+    let instrs = match typ {
+        Type::Int(ast::IntTy::I8 | ast::IntTy::U8) | Type::Bool => vec![I32Store8(m)],
+        Type::Int(ast::IntTy::I16 | ast::IntTy::U16) => vec![I32Store16(m)],
+        Type::Int(ast::IntTy::I32 | ast::IntTy::U32) | Type::Fn(_, _) => vec![I32Store(m)],
+        Type::Int(ast::IntTy::I64 | ast::IntTy::U64) => vec![I64Store(m)],
+        Type::Float(ast::FloatTy::F32) => vec![F32Store(m)],
+        Type::Float(ast::FloatTy::F64) => vec![F64Store(m)],
+        Type::String => {
+            let (ctx, len) = ctx.scratch_local(i32);
+            let (ctx, ptr) = ctx.scratch_local(i32);
+            let (ctx, addr) = ctx.scratch_local(i32);
+            let instrs = vec![
+                LocalSet(len),
+                LocalSet(ptr),
+                LocalTee(addr),
+                LocalGet(ptr),
+                I32Store(m),
+                LocalGet(addr),
+                LocalGet(len),
+                I32Store(mem_arg(offset + 4, typ)),
+            ];
+            return (ctx, instrs);
+        }
+        Type::Struct(_) | Type::Enum(_) | Type::Tuple(_) => {
+            let (ctx, src) = ctx.scratch_local(i32);
+            let instrs = vec![
+                LocalSet(src),
+                I32Const(offset),
+                I32Add,
+                LocalGet(src),
+                I32Const(typ.size()),
+                MemoryCopy,
+            ];
+            return (ctx, instrs);
+        }
+        Type::Alias(alias) => return store_to(ctx, &alias.typ, offset),
+        Type::Unit | Type::Never | Type::Error => vec![Drop],
+    };
+    (ctx.clone(), instrs)
+}
+
+// The allocator bumps the heap global by the requested size, rounded up to
+// `HEAP_ALIGN`, and grows the memory when the heap runs past its end.
+fn alloc_body(heap: wasm::GlobalIdx) -> Vec<wasm::Instr> {
+    use wasm::Instr::*;
+    let (size, ptr) = (0, 1);
+    let align = HEAP_ALIGN as wasm::U32;
+    let memory_end = || vec![MemorySize, I32Const(16), I32Shl];
+    let mut grow = vec![GlobalGet(heap)];
+    grow.append(&mut memory_end());
+    grow.extend([I32LeU, BrIf(0), GlobalGet(heap)]);
+    grow.append(&mut memory_end());
+    grow.extend([
+        I32Sub,
+        I32Const(PAGE_SIZE as wasm::U32 - 1),
+        I32Add,
+        I32Const(16),
+        I32ShrU,
+        MemoryGrow,
+        Drop,
+    ]);
+    vec![
+        GlobalGet(heap),
+        LocalTee(ptr),
+        LocalGet(size),
+        I32Add,
+        I32Const(align - 1),
+        I32Add,
+        I32Const(!(align - 1)),
+        I32And,
+        GlobalSet(heap),
+        Block(wasm::BlockType::None, grow),
+        LocalGet(ptr),
+    ]
 }
 
 impl WASMTranslator<ast::Stmt, wasm::Expr> for AST {
-    fn to_wasm(ctx: &Context, node: &ast::Stmt) -> (Context, wasm::Expr) {
+    fn to_wasm(ctx: &Context, b: &mut Builder, node: &ast::Stmt) -> (Context, wasm::Expr) {
         match node {
-            ast::Stmt::Expr(e) => AST::to_wasm(ctx, e),
-            ast::Stmt::Decl(_, _) => (ctx.clone(), wasm::Expr::new()),
+            ast::Stmt::Expr(e) => AST::to_wasm(ctx, b, e),
+            ast::Stmt::Decl(decl)
+                if !decl.mutable && matches!(decl.expr.kind, ast::ExprKind::Fn(..)) =>
+            {
+                let index = lift_fn(ctx, b, &decl.expr);
+                (
+                    ctx.bind(&decl.name, Binding::Func(index)),
+                    wasm::Expr::new(),
+                )
+            }
+            ast::Stmt::Decl(decl) => {
+                let (ctx, mut expr) = AST::to_wasm(ctx, b, &decl.expr);
+                let (ctx, mut copy) = copy_if_shared(&ctx, b, &decl.expr);
+                expr.instrs.append(&mut copy);
+                let (ctx, binding) = ctx.declare(b, &decl.name, typ_of(&decl.expr));
+                expr.instrs.append(&mut store(binding));
+                (ctx, expr)
+            }
+            // A field is assigned through the address of its struct, which
+            // is kept for the load of a compound assignment.
+            ast::Stmt::Assign(assign) if matches!(assign.target.kind, ast::ExprKind::Field(..)) => {
+                let typ = typ_of(&assign.target);
+                let (ctx, mut expr, offset) = place_to_wasm(ctx, b, &assign.target);
+                let ctx = match assign.op {
+                    Some(_) => {
+                        let i32 = wasm::ValType::NumType(wasm::NumType::I32);
+                        let (ctx, addr) = ctx.scratch_local(i32);
+                        expr.instrs.push(wasm::Instr::LocalTee(addr));
+                        expr.instrs.push(wasm::Instr::LocalGet(addr));
+                        let (ctx, mut load) = load_from(&ctx, typ, offset);
+                        expr.instrs.append(&mut load);
+                        ctx
+                    }
+                    None => ctx,
+                };
+                let (ctx, mut value) = AST::to_wasm(&ctx, b, &assign.expr);
+                expr.append(&mut value);
+                let ctx = match assign.op {
+                    Some(op) => {
+                        let (ctx, mut opwasm) = AST::to_wasm(&ctx, b, &(op, typ.clone()));
+                        expr.append(&mut opwasm);
+                        ctx
+                    }
+                    None => ctx,
+                };
+                let (ctx, mut store) = store_to(&ctx, typ, offset);
+                expr.instrs.append(&mut store);
+                (ctx, expr)
+            }
+            ast::Stmt::Assign(assign) => {
+                let name = match &assign.target.kind {
+                    ast::ExprKind::Var(name) => name,
+                    _ => unreachable!("assignment target is not a place"),
+                };
+                let binding = ctx.lookup(name);
+                let mut expr = wasm::Expr::new();
+                if assign.op.is_some() {
+                    expr.instrs.append(&mut load(binding));
+                }
+                let (ctx, mut value) = AST::to_wasm(ctx, b, &assign.expr);
+                expr.append(&mut value);
+                let ctx = match assign.op {
+                    Some(op) => {
+                        let typ = typ_of(&assign.expr).clone();
+                        let (ctx, mut opwasm) = AST::to_wasm(&ctx, b, &(op, typ));
+                        expr.append(&mut opwasm);
+                        ctx
+                    }
+                    None => {
+                        let (ctx, mut copy) = copy_if_shared(&ctx, b, &assign.expr);
+                        expr.instrs.append(&mut copy);
+                        ctx
+                    }
+                };
+                expr.instrs.append(&mut store(binding));
+                (ctx, expr)
+            }
+            ast::Stmt::Fn(_) => unreachable!("function items are lowered by stmts_to_wasm"),
         }
     }
 }
 
-impl WASMTranslator<ast::BinOp, wasm::Expr> for AST {
-    fn to_wasm(ctx: &Context, node: &ast::BinOp) -> (Context, wasm::Expr) {
-        match node {
-            ast::BinOp::Add => (ctx.clone(), wasm::Expr::from(wasm::Instr::I64Add)),
-            ast::BinOp::Minus => (ctx.clone(), wasm::Expr::from(wasm::Instr::I64Sub)),
-            ast::BinOp::Times => (ctx.clone(), wasm::Expr::from(wasm::Instr::I64Mul)),
-            ast::BinOp::Divide => (ctx.clone(), wasm::Expr::from(wasm::Instr::I64DivU)),
+fn load(binding: Binding) -> Vec<wasm::Instr> {
+    match binding {
+        Binding::Local(index, n) => (index..index + n as wasm::LocalIdx)
+            .map(wasm::Instr::LocalGet)
+            .collect(),
+        Binding::Global(index, n) => (index..index + n as wasm::GlobalIdx)
+            .map(wasm::Instr::GlobalGet)
+            .collect(),
+        Binding::Func(_) => unreachable!("functions are not loaded from storage"),
+    }
+}
+
+// The last value is on top of the stack, so values are stored backwards.
+fn store(binding: Binding) -> Vec<wasm::Instr> {
+    match binding {
+        Binding::Local(index, n) => (index..index + n as wasm::LocalIdx)
+            .rev()
+            .map(wasm::Instr::LocalSet)
+            .collect(),
+        Binding::Global(index, n) => (index..index + n as wasm::GlobalIdx)
+            .rev()
+            .map(wasm::Instr::GlobalSet)
+            .collect(),
+        Binding::Func(_) => unreachable!("functions are never assigned"),
+    }
+}
+
+// Keeps the i32 representation of 8 and 16 bit integers in range after an
+// operation that may overflow it.
+fn wrap_narrow(t: ast::IntTy) -> Vec<wasm::Instr> {
+    match t {
+        ast::IntTy::I8 => vec![wasm::Instr::I32Extend8S],
+        ast::IntTy::I16 => vec![wasm::Instr::I32Extend16S],
+        ast::IntTy::U8 => vec![wasm::Instr::I32Const(0xFF), wasm::Instr::I32And],
+        ast::IntTy::U16 => vec![wasm::Instr::I32Const(0xFFFF), wasm::Instr::I32And],
+        _ => Vec::new(),
+    }
+}
+
+fn int_binop(op: ast::BinOp, t: ast::IntTy) -> Vec<wasm::Instr> {
+    use ast::BinOp::*;
+    use wasm::Instr::*;
+    let wide = matches!(t, ast::IntTy::I64 | ast::IntTy::U64);
+    let w = |i64: wasm::Instr, i32: wasm::Instr| if wide { i64 } else { i32 };
+    let s = |signed: wasm::Instr, unsigned: wasm::Instr| {
+        if t.is_signed() {
+            signed
+        } else {
+            unsigned
         }
+    };
+    let instr = match op {
+        Add => w(I64Add, I32Add),
+        Minus => w(I64Sub, I32Sub),
+        Times => w(I64Mul, I32Mul),
+        Divide => s(w(I64DivS, I32DivS), w(I64DivU, I32DivU)),
+        Rem => s(w(I64RemS, I32RemS), w(I64RemU, I32RemU)),
+        BitAnd => w(I64And, I32And),
+        BitOr => w(I64Or, I32Or),
+        BitXor => w(I64Xor, I32Xor),
+        Shl => w(I64Shl, I32Shl),
+        Shr => s(w(I64ShrS, I32ShrS), w(I64ShrU, I32ShrU)),
+        Eq => w(I64Eq, I32Eq),
+        Ne => w(I64Ne, I32Ne),
+        Lt => s(w(I64LtS, I32LtS), w(I64LtU, I32LtU)),
+        Le => s(w(I64LeS, I32LeS), w(I64LeU, I32LeU)),
+        Gt => s(w(I64GtS, I32GtS), w(I64GtU, I32GtU)),
+        Ge => s(w(I64GeS, I32GeS), w(I64GeU, I32GeU)),
+        And | Or => unreachable!("logical operators are lowered to `if`"),
+    };
+    let mut instrs = vec![instr];
+    if let Add | Minus | Times | Divide | Shl = op {
+        instrs.append(&mut wrap_narrow(t));
+    }
+    instrs
+}
+
+fn float_binop(op: ast::BinOp, t: ast::FloatTy) -> wasm::Instr {
+    use ast::BinOp::*;
+    use wasm::Instr::*;
+    let w = |f64: wasm::Instr, f32: wasm::Instr| match t {
+        ast::FloatTy::F64 => f64,
+        ast::FloatTy::F32 => f32,
+    };
+    match op {
+        Add => w(F64Add, F32Add),
+        Minus => w(F64Sub, F32Sub),
+        Times => w(F64Mul, F32Mul),
+        Divide => w(F64Div, F32Div),
+        Eq => w(F64Eq, F32Eq),
+        Ne => w(F64Ne, F32Ne),
+        Lt => w(F64Lt, F32Lt),
+        Le => w(F64Le, F32Le),
+        Gt => w(F64Gt, F32Gt),
+        Ge => w(F64Ge, F32Ge),
+        _ => unreachable!("`{}` on floats after type checking", op),
+    }
+}
+
+// Operators are lowered by the type of their (left) operand.
+impl WASMTranslator<(ast::BinOp, Type), wasm::Expr> for AST {
+    fn to_wasm(ctx: &Context, _: &mut Builder, node: &(ast::BinOp, Type)) -> (Context, wasm::Expr) {
+        let instrs = match node {
+            (op, Type::Int(t)) => int_binop(*op, *t),
+            (op, Type::Float(t)) => vec![float_binop(*op, *t)],
+            (op, Type::Bool) => vec![match op {
+                ast::BinOp::Eq => wasm::Instr::I32Eq,
+                ast::BinOp::Ne => wasm::Instr::I32Ne,
+                ast::BinOp::BitAnd => wasm::Instr::I32And,
+                ast::BinOp::BitOr => wasm::Instr::I32Or,
+                ast::BinOp::BitXor => wasm::Instr::I32Xor,
+                _ => unreachable!("`{}` on bools after type checking", op),
+            }],
+            (op, typ) => unreachable!("`{}` on `{}` after type checking", op, typ),
+        };
+        (ctx.clone(), wasm::Expr::from(instrs))
+    }
+}
+
+// Expects the operand on the stack, preceded by a zero for integer negation.
+impl WASMTranslator<(ast::UnOp, Type), wasm::Expr> for AST {
+    fn to_wasm(ctx: &Context, _: &mut Builder, node: &(ast::UnOp, Type)) -> (Context, wasm::Expr) {
+        let instrs = match node {
+            (ast::UnOp::Neg, Type::Int(t)) => int_binop(ast::BinOp::Minus, *t),
+            (ast::UnOp::Neg, Type::Float(ast::FloatTy::F32)) => vec![wasm::Instr::F32Neg],
+            (ast::UnOp::Neg, Type::Float(ast::FloatTy::F64)) => vec![wasm::Instr::F64Neg],
+            // Bitwise not is xor with all ones.
+            (ast::UnOp::Not, Type::Int(t @ (ast::IntTy::I64 | ast::IntTy::U64))) => {
+                let mut instrs = vec![wasm::Instr::I64Const(u64::MAX)];
+                instrs.append(&mut int_binop(ast::BinOp::BitXor, *t));
+                instrs
+            }
+            (ast::UnOp::Not, Type::Int(t)) => {
+                let mut instrs = vec![wasm::Instr::I32Const(u32::MAX)];
+                instrs.append(&mut int_binop(ast::BinOp::BitXor, *t));
+                instrs.append(&mut wrap_narrow(*t));
+                instrs
+            }
+            (ast::UnOp::Not, Type::Bool) => vec![wasm::Instr::I32Eqz],
+            (op, typ) => unreachable!("unary `{}` on `{}` after type checking", op, typ),
+        };
+        (ctx.clone(), wasm::Expr::from(instrs))
     }
 }
 
 impl WASMTranslator<ast::Literal, wasm::Expr> for AST {
-    fn to_wasm(ctx: &Context, node: &ast::Literal) -> (Context, wasm::Expr) {
+    fn to_wasm(ctx: &Context, b: &mut Builder, node: &ast::Literal) -> (Context, wasm::Expr) {
         match node {
-            ast::Literal::Int(u) => (ctx.clone(), wasm::Expr::from(wasm::Instr::I64Const(*u))),
-            ast::Literal::Float(z) => (ctx.clone(), wasm::Expr::from(wasm::Instr::F64Const(*z))),
+            ast::Literal::Int(u, typ) => match typ {
+                Some(ast::IntTy::I64 | ast::IntTy::U64) | None => {
+                    (ctx.clone(), wasm::Expr::from(wasm::Instr::I64Const(*u)))
+                }
+                Some(_) => (
+                    ctx.clone(),
+                    wasm::Expr::from(wasm::Instr::I32Const(*u as wasm::U32)),
+                ),
+            },
+            ast::Literal::Float(z, typ) => match typ {
+                Some(ast::FloatTy::F32) => (
+                    ctx.clone(),
+                    wasm::Expr::from(wasm::Instr::F32Const(*z as wasm::F32)),
+                ),
+                _ => (ctx.clone(), wasm::Expr::from(wasm::Instr::F64Const(*z))),
+            },
+            // Strings live in a data segment and evaluate to a (ptr, len) pair.
+            ast::Literal::String(s) => {
+                let ptr = b.add_data(s.as_bytes());
+                let expr = wasm::Expr::from(vec![
+                    wasm::Instr::I32Const(ptr as wasm::U32),
+                    wasm::Instr::I32Const(s.len() as wasm::U32),
+                ]);
+                (ctx.clone(), expr)
+            }
             ast::Literal::Bool(b) => (
                 ctx.clone(),
-                wasm::Expr::from(wasm::Instr::I64Const(*b as u64)),
+                wasm::Expr::from(wasm::Instr::I32Const(*b as wasm::U32)),
             ),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::tests::load_files;
+    use crate::typeck;
+
+    // Compiles `files`, the first one being the root module, and returns
+    // the emitted wasm modules.
+    fn compile(files: &[(&str, &str)]) -> Vec<(Option<ast::Ident>, wasm::Module)> {
+        let (mut program, parse_errors, errors) = load_files(files);
+        assert!(parse_errors.is_empty(), "{:?}", parse_errors);
+        assert!(errors.is_empty(), "{:?}", errors);
+        let errors = typeck::check(&mut program);
+        assert!(errors.is_empty(), "{:?}", errors);
+        translate(&program)
+    }
+
+    fn exports(module: &wasm::Module) -> Vec<String> {
+        let kind = |desc: &wasm::ExportDesc| match desc {
+            wasm::ExportDesc::Func(_) => "func",
+            wasm::ExportDesc::Table(_) => "table",
+            wasm::ExportDesc::Mem(_) => "mem",
+            wasm::ExportDesc::Global(_) => "global",
+        };
+        let exports = module.exports.iter();
+        exports
+            .map(|e| format!("{} {}", kind(&e.desc), e.name))
+            .collect()
+    }
+
+    fn imports(module: &wasm::Module) -> Vec<String> {
+        let imports = module.imports.iter();
+        imports
+            .map(|i| format!("{}.{}", i.module, i.name))
+            .collect()
+    }
+
+    // The body of the exported function `name`.
+    fn body<'a>(module: &'a wasm::Module, name: &str) -> &'a [wasm::Instr] {
+        let index = module.exports.iter().find_map(|e| match e.desc {
+            wasm::ExportDesc::Func(index) if e.name == name => Some(index),
+            _ => None,
+        });
+        let index = index.unwrap() as usize - module.imports.len();
+        &module.funcs[index].body.instrs
+    }
+
+    #[test]
+    fn calls() {
+        let units = compile(&[(
+            "main.sd",
+            "fn add(a: int, b: int) -> int { a + b }\nfn main() -> int {\n    let f = (x: int) => x * 2\n    let mut g = f\n    add(f(1), g(2))\n}\n",
+        )]);
+        let [(None, module)] = &units[..] else {
+            panic!("expected the main module alone");
+        };
+        assert_eq!(exports(module), ["func main", "mem memory"]);
+        assert!(imports(module).is_empty());
+        assert_eq!(module.funcs.len(), 3);
+        let body = body(module, "main");
+        assert!(body.iter().any(|i| matches!(i, wasm::Instr::Call(_))));
+        assert!(body
+            .iter()
+            .any(|i| matches!(i, wasm::Instr::CallIndirect(0, _))));
+        assert_eq!(module.tables.len(), 1);
+    }
+
+    #[test]
+    fn fn_items() {
+        let units = compile(&[(
+            "main.sd",
+            "fn even(n: int) -> bool { if n == 0 { true } else { odd(n - 1) } }\nfn main() -> int { if even(10) { 1 } else { 0 } }\nfn odd(n: int) -> bool { if n == 0 { false } else { even(n - 1) } }\n",
+        )]);
+        let [(None, module)] = &units[..] else {
+            panic!("expected the main module alone");
+        };
+        assert_eq!(exports(module), ["func main", "mem memory"]);
+        assert!(imports(module).is_empty());
+        // `even` and `odd` share their type.
+        assert_eq!(module.funcs.len(), 3);
+        assert_eq!(module.types.len(), 2);
+        assert_eq!(module.funcs[0].typ, module.funcs[2].typ);
+    }
+
+    #[test]
+    fn methods() {
+        let units = compile(&[(
+            "main.sd",
+            "shard geo {\n    struct Point { x: float, y: float }\n    impl Point {\n        fn new(x: float, y: float) -> Point { Point { x, y } }\n        fn len(self) -> float { self.x * self.x + self.y * self.y }\n    }\n}\n",
+        )]);
+        let [(Some(shard), module)] = &units[..] else {
+            panic!("expected the shard alone");
+        };
+        assert_eq!(shard, "geo");
+        assert_eq!(
+            exports(module),
+            ["func Point::new", "func Point::len", "mem memory"]
+        );
+        // `self` is passed as the address of the struct.
+        let len = &module.funcs[1];
+        let params = &module.types[len.typ as usize].from.values;
+        assert!(matches!(
+            params[..],
+            [wasm::ValType::NumType(wasm::NumType::I32)]
+        ));
+    }
+
+    #[test]
+    fn linked_modules() {
+        let units = compile(&[
+            ("main.sd", "use a\nuse a::b::{g}\nfn f() -> int { 1 }\nfn main() -> int { a::f() + f() + g() }\n"),
+            ("a.sd", "use a::b\nfn f() -> int { b::g() }\n"),
+            ("a/b.sd", "fn g() -> int { 3 }\n"),
+        ]);
+        let [(None, module)] = &units[..] else {
+            panic!("expected the main module alone");
+        };
+        assert_eq!(exports(module), ["func main", "mem memory"]);
+        assert_eq!(module.funcs.len(), 4);
+    }
+
+    #[test]
+    fn shards() {
+        let units = compile(&[
+            ("main.sd", "use lib::{BASE}\nextern fn print(x: int)\nextern fn unused(x: int)\nshard math {\n    const SCALE: int = 10\n    const NAME: string = \"math\"\n    fn add(a: int, b: int) -> int { a * SCALE + b + BASE }\n}\nshard stats {\n    fn show(a: int) -> int {\n        print(a)\n        math::add(a, 1)\n    }\n}\nfn main() -> int { stats::show(4) + math::add(1, 2) }\n"),
+            ("lib.sd", "const BASE: int = 7\n"),
+        ]);
+        let [(None, main), (Some(math), math_module), (Some(stats), stats_module)] = &units[..]
+        else {
+            panic!("expected the main module and two shards");
+        };
+        assert_eq!((math.as_str(), stats.as_str()), ("math", "stats"));
+        // Every extern declared by the root is imported by it, but a shard
+        // only imports those it calls.
+        assert_eq!(exports(main), ["func main", "mem memory"]);
+        assert_eq!(
+            imports(main),
+            ["env.print", "env.unused", "stats.show", "math.add"]
+        );
+        assert_eq!(
+            exports(math_module),
+            [
+                "global SCALE",
+                "global NAME.ptr",
+                "global NAME.len",
+                "func add",
+                "mem memory"
+            ]
+        );
+        assert!(imports(math_module).is_empty());
+        assert_eq!(exports(stats_module), ["func show", "mem memory"]);
+        assert_eq!(imports(stats_module), ["env.print", "math.add"]);
+    }
+
+    #[test]
+    fn local_items() {
+        let units = compile(&[(
+            "main.sd",
+            "shard math {\n    local const K: int = 2\n    const SCALE: int = 3\n    local fn helper(x: int) -> int { x * K * SCALE }\n    fn twice(x: int) -> int { helper(x) }\n}\n",
+        )]);
+        let [(Some(_), module)] = &units[..] else {
+            panic!("expected the shard alone");
+        };
+        assert_eq!(
+            exports(module),
+            ["global SCALE", "func twice", "mem memory"]
+        );
+    }
+}
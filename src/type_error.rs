@@ -0,0 +1,37 @@
+use super::token::Span;
+use std::fmt;
+
+#[derive(Debug, Clone)]
+pub struct TypeError {
+    pub span: Span,
+    pub message: String,
+    // Warnings are reported, but do not stop compilation.
+    pub is_warning: bool,
+}
+
+impl TypeError {
+    pub fn new(span: Span, message: String) -> TypeError {
+        TypeError {
+            span,
+            message,
+            is_warning: false,
+        }
+    }
+
+    pub fn warning(span: Span, message: String) -> TypeError {
+        TypeError {
+            span,
+            message,
+            is_warning: true,
+        }
+    }
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_warning {
+            f.write_str("warning: ")?;
+        }
+        f.write_str(&self.message)
+    }
+}
@@ -0,0 +1,2343 @@
+use super::ast::*;
+use super::const_eval;
+use super::exhaustiveness::{self, Ctor, Pat};
+use super::token::Span;
+use super::type_error::TypeError;
+use super::types::{AliasType, EnumType, StructType, Type, VariantKind, VariantType};
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+pub struct TypeChecker {
+    // The first scope holds the program-level bindings.
+    scopes: Vec<HashMap<Ident, Binding>>,
+    // Index of the first scope of the innermost function being checked.
+    fn_scope: usize,
+    // The loops enclosing the expression being checked, innermost last.
+    loops: Vec<LoopScope>,
+    // The type of the values returned so far from the innermost function.
+    ret: Option<Type>,
+    // Struct, enum and alias declarations by name. Structs and enums are
+    // resolved into `named_types` on first use so that they may refer to
+    // each other in any order, and aliases are expanded at every use.
+    type_decls: HashMap<Ident, (TypeDecl, Span)>,
+    named_types: HashMap<Ident, Type>,
+    // The types whose fields are being resolved and the aliases being
+    // expanded, to catch recursion.
+    resolving: Vec<Ident>,
+    // The generic parameters of the alias being expanded, bound to its
+    // arguments.
+    type_params: HashMap<Ident, Type>,
+    // The functions of `impl` blocks by path, like `Point::new`, and whether
+    // each is a method. They are declared in the program scope under that
+    // path.
+    assoc_fns: HashMap<Ident, bool>,
+    // The names in scope in each module, and the index of the module being
+    // checked. Items are bound in the program scope by their linked names.
+    namespaces: Vec<Namespace>,
+    module: usize,
+    // The module of every function item by linked name, and the functions
+    // of other shards each module refers to, with where it first does.
+    fn_modules: HashMap<Ident, usize>,
+    imports: Vec<Vec<(Ident, Span)>>,
+    // The same for constants and external functions, with those of other
+    // modules each module refers to.
+    const_modules: HashMap<Ident, usize>,
+    consts: Vec<Vec<Ident>>,
+    extern_modules: HashMap<Ident, usize>,
+    externs: Vec<Vec<Ident>>,
+    // The `local` items by linked name, with the module that declares them,
+    // what they are and where, and those referred to so far.
+    local_items: HashMap<Ident, (usize, &'static str, Span)>,
+    used_items: HashSet<Ident>,
+    errors: Vec<TypeError>,
+}
+
+#[derive(Default)]
+struct Namespace {
+    path: Ident,
+    shard: Option<Ident>,
+    // The namespace of the module that declares the shard.
+    parent: Option<usize>,
+    // The linked names of the items of the module and of those it uses.
+    items: HashMap<Ident, Ident>,
+    // The paths of the modules it uses.
+    modules: HashMap<Ident, Ident>,
+}
+
+struct LoopScope {
+    label: Option<Label>,
+    keyword: &'static str,
+    // Only `loop` can break with a value. `expected` is the type hint of
+    // the loop and `break_typ` the type of the values broken with so far.
+    expected: Option<Type>,
+    break_typ: Option<Type>,
+}
+
+#[derive(Clone)]
+enum TypeDecl {
+    Struct(Vec<Field>),
+    Enum(Vec<Variant>),
+    // The generic parameters and the aliased type.
+    Alias(Vec<Ident>, TypeExpr),
+}
+
+struct Binding {
+    typ: Type,
+    mutable: bool,
+    // Bound directly to a function literal or item, so referring to it needs no
+    // access to the enclosing function's locals.
+    is_static_fn: bool,
+}
+
+// Resolves the names in `program` and annotates every expression with its
+// type. Returns the errors found on the way.
+pub fn check(program: &mut Program) -> Vec<TypeError> {
+    let mut checker = TypeChecker::new();
+    checker.check_program(program);
+    // Constants can only be evaluated once their initializers are known to
+    // be well typed.
+    if !checker.errors.iter().any(|err| !err.is_warning) {
+        checker.errors.extend(const_eval::eval_consts(program));
+    }
+    checker.errors
+}
+
+impl TypeChecker {
+    pub fn new() -> TypeChecker {
+        TypeChecker {
+            scopes: vec![HashMap::new()],
+            fn_scope: 0,
+            loops: Vec::new(),
+            ret: None,
+            type_decls: HashMap::new(),
+            named_types: HashMap::new(),
+            resolving: Vec::new(),
+            type_params: HashMap::new(),
+            assoc_fns: HashMap::new(),
+            namespaces: Vec::new(),
+            module: 0,
+            fn_modules: HashMap::new(),
+            imports: Vec::new(),
+            const_modules: HashMap::new(),
+            consts: Vec::new(),
+            extern_modules: HashMap::new(),
+            externs: Vec::new(),
+            local_items: HashMap::new(),
+            used_items: HashSet::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    fn error(&mut self, span: Span, message: String) {
+        // Aliases are expanded at every use, which would repeat the errors
+        // in their definitions.
+        let repeated = |err: &TypeError| err.span == span && err.message == message;
+        if self.errors.iter().any(repeated) {
+            return;
+        }
+        self.errors.push(TypeError::new(span, message));
+    }
+
+    fn warning(&mut self, span: Span, message: String) {
+        self.errors.push(TypeError::warning(span, message));
+    }
+
+    // Merges `typ` into `prev`, the type of the values seen so far at a
+    // point where control flow meets, and reports a mismatch at `span`.
+    fn merge(&mut self, prev: Option<Type>, typ: Type, span: &Span) -> Type {
+        match prev {
+            None => typ,
+            Some(Type::Error) => Type::Error,
+            Some(_) if typ == Type::Error => Type::Error,
+            Some(prev) => match join(&prev, &typ) {
+                Some(joined) => joined,
+                None => {
+                    let message = format!("expected `{}`, found `{}`", prev, typ);
+                    self.error(span.clone(), message);
+                    prev
+                }
+            },
+        }
+    }
+
+    // The return type of a function whose body has type `body_typ`.
+    fn return_type(&mut self, body_typ: Type, span: &Span) -> Type {
+        let ret = self.ret.take();
+        self.merge(ret, body_typ, span)
+    }
+
+    // Returns the binding of `name` and the index of the scope it was found in.
+    fn lookup(&self, name: &str) -> Option<(usize, &Binding)> {
+        let local = self.scopes[1..]
+            .iter()
+            .enumerate()
+            .rev()
+            .find_map(|(depth, scope)| scope.get(name).map(|b| (depth + 1, b)));
+        // Variables are renamed to the linked names of the items they refer
+        // to, which are paths like `a::f`.
+        local.or_else(|| {
+            let binding = match name.contains("::") {
+                true => self.scopes[0].get(name)?,
+                false => self.scopes[0].get(&self.item_name(name)?)?,
+            };
+            Some((0, binding))
+        })
+    }
+
+    // The namespaces of the current module, innermost first. A shard sees
+    // the names of the module that declares it.
+    fn visible_namespaces(&self) -> impl Iterator<Item = &Namespace> {
+        let first = Some(&self.namespaces[self.module]);
+        std::iter::successors(first, |ns| ns.parent.map(|parent| &self.namespaces[parent]))
+    }
+
+    // The linked name of the item written as `name` in the current module,
+    // where `module::name` is an item of a module it uses.
+    fn item_name(&self, name: &str) -> Option<Ident> {
+        self.visible_namespaces()
+            .find_map(|namespace| match name.split_once("::") {
+                Some((module, rest)) => {
+                    Some(format!("{}::{}", namespace.modules.get(module)?, rest))
+                }
+                None => namespace.items.get(name).cloned(),
+            })
+    }
+
+    // The path of the module written as `name` in the current module, like
+    // `a::b` for `b` or for `b::shard`, the path of a shard of `a::b`.
+    fn module_name(&self, name: &str) -> Option<Ident> {
+        let (first, rest) = match name.split_once("::") {
+            Some((first, rest)) => (first, Some(rest)),
+            None => (name, None),
+        };
+        let module = self
+            .visible_namespaces()
+            .find_map(|namespace| namespace.modules.get(first))?;
+        let path = match rest {
+            Some(rest) => format!("{}::{}", module, rest),
+            None => module.clone(),
+        };
+        self.namespaces
+            .iter()
+            .any(|namespace| namespace.path == path)
+            .then_some(path)
+    }
+
+    // The struct or enum `name` of the current module, if resolved. A local
+    // struct out of reach is reported at `span`, and is an error type.
+    fn named_type(&mut self, name: &str, span: &Span) -> Option<Type> {
+        let linked = self.item_name(name)?;
+        let typ = self.named_types.get(&linked).cloned()?;
+        match self.use_item(&linked, span) {
+            true => Some(typ),
+            false => Some(Type::Error),
+        }
+    }
+
+    // Records a reference to the item linked as `name`. A local item can
+    // only be referred to from its module, and from the shards it declares;
+    // elsewhere the reference is reported and this returns false.
+    fn use_item(&mut self, name: &str, span: &Span) -> bool {
+        if let Some(message) = self.local_error(name) {
+            self.error(span.clone(), message);
+            return false;
+        }
+        if self.local_items.contains_key(name) {
+            self.used_items.insert(name.to_string());
+        }
+        true
+    }
+
+    // The error for a reference to the item `name` from the current module,
+    // if it is a local item of another.
+    fn local_error(&self, name: &str) -> Option<String> {
+        let &(module, ..) = self.local_items.get(name)?;
+        let declared_in = &self.namespaces[module];
+        if self
+            .visible_namespaces()
+            .any(|namespace| namespace.path == declared_in.path)
+        {
+            return None;
+        }
+        let owner = match &declared_in.shard {
+            Some(shard) => format!("shard `{}`", shard),
+            None => format!("module `{}`", declared_in.path),
+        };
+        Some(format!("`{}` is local to {}", name, owner))
+    }
+
+    // The index of the module that declares the item with the linked name
+    // `name`.
+    fn module_of(&self, name: &str) -> usize {
+        let path = name.rsplit_once("::").map_or("", |(path, _)| path);
+        let found = self.namespaces.iter().position(|ns| ns.path == path);
+        found.unwrap_or(self.module)
+    }
+
+    // Looks up `name` from the current function, reporting failures. An
+    // item is renamed to its linked name. Returns its type and whether it
+    // is mutable.
+    fn resolve(&mut self, name: &mut Ident, span: &Span) -> Option<(Type, bool)> {
+        if let Some((0, binding)) = self.lookup(name) {
+            if !name.contains("::") {
+                *name = self.item_name(name).unwrap();
+            }
+            let typ = binding.typ.clone();
+            if !self.use_item(name, span) {
+                return None;
+            }
+            self.check_shard_access(name, &typ, span);
+            self.use_foreign(name);
+        }
+        match self.lookup(name) {
+            // Functions are not closures: the locals of an enclosing
+            // function are out of reach once it is lifted.
+            Some((depth, binding))
+                if depth > 0 && depth < self.fn_scope && !binding.is_static_fn =>
+            {
+                let message = format!("cannot capture local `{}` in a function", name);
+                self.error(span.clone(), message);
+                None
+            }
+            Some((_, binding)) => Some((binding.typ.clone(), binding.mutable)),
+            None => {
+                let message = format!("cannot find value `{}` in this scope", name);
+                self.error(span.clone(), message);
+                None
+            }
+        }
+    }
+
+    fn declare(&mut self, name: &str, typ: Type) {
+        let binding = Binding {
+            typ,
+            mutable: false,
+            is_static_fn: false,
+        };
+        self.declare_binding(name, binding);
+    }
+
+    fn declare_binding(&mut self, name: &str, binding: Binding) {
+        self.scopes
+            .last_mut()
+            .unwrap()
+            .insert(name.to_string(), binding);
+    }
+
+    pub fn check_program(&mut self, program: &mut Program) {
+        self.declare_namespaces(program);
+        for item in &program.items {
+            let (name, decl, span) = match item {
+                Item::Struct(decl) => (
+                    &decl.name,
+                    TypeDecl::Struct(decl.fields.clone()),
+                    &decl.span,
+                ),
+                Item::Enum(decl) => (
+                    &decl.name,
+                    TypeDecl::Enum(decl.variants.clone()),
+                    &decl.span,
+                ),
+                Item::Alias(decl) => (
+                    &decl.name,
+                    TypeDecl::Alias(decl.params.clone(), decl.typ.clone()),
+                    &decl.span,
+                ),
+                _ => continue,
+            };
+            if self.type_decls.contains_key(name) {
+                let message = format!("the type `{}` is defined multiple times", name);
+                self.error(span.clone(), message);
+                continue;
+            }
+            self.type_decls.insert(name.clone(), (decl, span.clone()));
+        }
+        let mut const_types = Vec::new();
+        for (index, module) in program.modules.iter().enumerate() {
+            self.module = index;
+            for item in program.items[module.items.clone()].iter_mut() {
+                match item {
+                    Item::Fn(decl) => {
+                        let typ =
+                            self.declare_fn(&decl.name, &decl.args, decl.ret.as_ref(), &decl.span);
+                        decl.typ = Some(typ);
+                        self.fn_modules.insert(decl.name.clone(), index);
+                    }
+                    Item::Extern(ext) => {
+                        let typ =
+                            self.declare_fn(&ext.name, &ext.args, ext.ret.as_ref(), &ext.span);
+                        ext.typ = Some(typ);
+                        self.extern_modules.insert(ext.name.clone(), index);
+                    }
+                    Item::Const(decl) => {
+                        let typ = self.check_type(&decl.typ);
+                        let binding = Binding {
+                            typ: typ.clone(),
+                            mutable: false,
+                            is_static_fn: false,
+                        };
+                        self.declare_item(&decl.name, binding, &decl.span);
+                        self.const_modules.insert(decl.name.clone(), index);
+                        const_types.push(typ);
+                    }
+                    // A type defined multiple times is checked as its
+                    // first definition only.
+                    Item::Struct(StructDecl { name, span, .. })
+                    | Item::Enum(EnumDecl { name, span, .. }) => {
+                        if self.type_decls[name].1 == *span {
+                            self.resolve_named(name);
+                        }
+                    }
+                    Item::Alias(decl) => {
+                        if self.type_decls[&decl.name].1 == decl.span {
+                            self.check_alias(decl);
+                        }
+                    }
+                    Item::Impl(block) => self.declare_impl(block),
+                    // Shards are linked as modules of their own.
+                    Item::Use(_) | Item::Shard(_) => (),
+                }
+            }
+        }
+        let mut const_types = const_types.into_iter();
+        for (index, module) in program.modules.iter().enumerate() {
+            self.module = index;
+            for item in program.items[module.items.clone()].iter_mut() {
+                match item {
+                    Item::Fn(decl) => self.check_fn(decl),
+                    // An initializer is checked like the body of a function
+                    // returning the constant's type.
+                    Item::Const(decl) => {
+                        let typ = const_types.next().unwrap();
+                        self.check_fn_body(&[], &[], Some(typ), &mut decl.expr);
+                    }
+                    // The functions of an `impl` for an unknown type are not
+                    // declared.
+                    Item::Impl(block) => {
+                        for decl in block.fns.iter_mut().filter(|decl| decl.typ.is_some()) {
+                            self.check_fn(decl);
+                        }
+                    }
+                    Item::Extern(_)
+                    | Item::Struct(_)
+                    | Item::Enum(_)
+                    | Item::Alias(_)
+                    | Item::Use(_)
+                    | Item::Shard(_) => (),
+                }
+            }
+        }
+
+        self.check_shard_cycles();
+        self.check_unused_locals();
+        for (module, imports) in program.modules.iter_mut().zip(&self.imports) {
+            module.imports = imports.iter().map(|(name, _)| name.clone()).collect();
+        }
+        for (index, module) in program.modules.iter_mut().enumerate() {
+            module.consts = std::mem::take(&mut self.consts[index]);
+            module.externs = std::mem::take(&mut self.externs[index]);
+        }
+
+        // A program of shards alone needs no `main`.
+        let main = program.items.iter().find_map(|item| match item {
+            Item::Fn(decl) if decl.name == "main" => Some(decl),
+            _ => None,
+        });
+        let has_shards = program.modules.iter().any(|module| module.shard.is_some());
+        match main {
+            Some(decl) if !decl.args.is_empty() => {
+                let message = String::from("`main` function takes no arguments");
+                self.error(decl.span.clone(), message);
+            }
+            None if !has_shards => self.error(0..0, String::from("`main` function not found")),
+            _ => (),
+        }
+    }
+
+    // Records a reference to the item `name` of type `typ`. A function of
+    // another shard is imported from the wasm module of that shard, so only
+    // values held in registers can be passed to it. Functions outside of
+    // shards are compiled into the main wasm module, out of reach of shards.
+    fn check_shard_access(&mut self, name: &str, typ: &Type, span: &Span) {
+        let module = match self.fn_modules.get(name) {
+            Some(&module) => module,
+            None => return,
+        };
+        let from = &self.namespaces[self.module].shard;
+        let message = match &self.namespaces[module].shard {
+            to if to == from => return,
+            None => format!(
+                "cannot use `{}` in shard `{}`, as it belongs to no shard",
+                name,
+                from.as_ref().unwrap()
+            ),
+            Some(to) => {
+                let mut types = match typ {
+                    Type::Fn(params, ret) => params.iter().chain([&**ret]),
+                    _ => unreachable!("function item of type `{}`", typ),
+                };
+                let in_memory = types.find(|typ| {
+                    !matches!(
+                        typ.unalias(),
+                        Type::Int(_)
+                            | Type::Float(_)
+                            | Type::Bool
+                            | Type::Unit
+                            | Type::Never
+                            | Type::Error
+                    )
+                });
+                match in_memory {
+                    Some(typ) => format!(
+                        "cannot import `{}` from shard `{}`, as values of type `{}` cannot be passed between shards",
+                        name, to, typ
+                    ),
+                    None => {
+                        let imports = &mut self.imports[self.module];
+                        if !imports.iter().any(|(import, _)| import == name) {
+                            imports.push((name.to_string(), span.clone()));
+                        }
+                        return;
+                    }
+                }
+            }
+        };
+        self.error(span.clone(), message);
+    }
+
+    // Records a reference to the constant or external function `name`, if
+    // it is one of another module.
+    fn use_foreign(&mut self, name: &str) {
+        let (module, names) = if let Some(&module) = self.const_modules.get(name) {
+            (module, &mut self.consts[self.module])
+        } else if let Some(&module) = self.extern_modules.get(name) {
+            (module, &mut self.externs[self.module])
+        } else {
+            return;
+        };
+        if module != self.module && !names.iter().any(|n| n == name) {
+            names.push(name.to_string());
+        }
+    }
+
+    // Warns about the local items never referred to, which nothing outside
+    // of their module could refer to either.
+    fn check_unused_locals(&mut self) {
+        let mut unused: Vec<_> = self
+            .local_items
+            .iter()
+            .filter(|(name, _)| !self.used_items.contains(*name))
+            .map(|(name, (_, what, span))| {
+                (span.clone(), format!("{} `{}` is never used", what, name))
+            })
+            .collect();
+        unused.sort_by_key(|(span, _)| span.start);
+        for (span, message) in unused {
+            self.warning(span, message);
+        }
+    }
+
+    // Reports shards that import each other, which no wasm runtime could
+    // instantiate.
+    fn check_shard_cycles(&mut self) {
+        // The shards each shard imports from, with where.
+        let mut edges: HashMap<&Ident, Vec<(&Ident, &Span)>> = HashMap::new();
+        for (module, imports) in self.imports.iter().enumerate() {
+            let from = match &self.namespaces[module].shard {
+                Some(from) => from,
+                None => continue,
+            };
+            for (name, span) in imports {
+                let to = self.namespaces[self.fn_modules[name]]
+                    .shard
+                    .as_ref()
+                    .unwrap();
+                edges.entry(from).or_default().push((to, span));
+            }
+        }
+        let mut done: HashSet<&Ident> = HashSet::new();
+        let mut errors = Vec::new();
+        let mut shards: Vec<&Ident> = edges.keys().copied().collect();
+        shards.sort();
+        for shard in shards {
+            let mut stack = vec![shard];
+            visit_shard(&edges, &mut stack, &mut done, &mut errors);
+        }
+        for (span, message) in errors {
+            self.error(span, message);
+        }
+    }
+
+    // Brings the items of every module into its namespace, along with the
+    // modules and items it uses.
+    fn declare_namespaces(&mut self, program: &Program) {
+        let mut declared = HashSet::new();
+        for (index, module) in program.modules.iter().enumerate() {
+            let mut namespace = Namespace {
+                path: module.path.clone(),
+                shard: module.shard.clone(),
+                ..Namespace::default()
+            };
+            for item in &program.items[module.items.clone()] {
+                let name = match item {
+                    Item::Fn(FnDecl { name, .. })
+                    | Item::Extern(ExternFn { name, .. })
+                    | Item::Const(ConstDecl { name, .. })
+                    | Item::Struct(StructDecl { name, .. })
+                    | Item::Enum(EnumDecl { name, .. })
+                    | Item::Alias(TypeAlias { name, .. }) => name,
+                    Item::Impl(_) | Item::Use(_) | Item::Shard(_) => continue,
+                };
+                let short = name.rsplit("::").next().unwrap();
+                namespace.items.insert(short.to_string(), name.clone());
+                declared.insert(name.clone());
+                let local = match item {
+                    Item::Fn(decl) if decl.local => Some(("function", &decl.span)),
+                    Item::Const(decl) if decl.local => Some(("constant", &decl.span)),
+                    Item::Struct(decl) if decl.local => Some(("struct", &decl.span)),
+                    _ => None,
+                };
+                if let Some((what, span)) = local {
+                    let local = (index, what, span.clone());
+                    self.local_items.insert(name.clone(), local);
+                }
+            }
+            self.namespaces.push(namespace);
+            self.imports.push(Vec::new());
+            self.consts.push(Vec::new());
+            self.externs.push(Vec::new());
+        }
+        for index in 0..self.namespaces.len() {
+            if self.namespaces[index].shard.is_some() {
+                let path = &self.namespaces[index].path;
+                let parent = path.rsplit_once("::").map_or("", |(parent, _)| parent);
+                let found = self.namespaces.iter().position(|ns| ns.path == parent);
+                self.namespaces[index].parent = found;
+            }
+        }
+        let mut shards: HashMap<&Ident, &Ident> = HashMap::new();
+        // A module only exports its own items, not those it uses.
+        for (index, module) in program.modules.iter().enumerate() {
+            self.module = index;
+            for item in &program.items[module.items.clone()] {
+                let decl = match item {
+                    Item::Use(decl) => decl,
+                    _ => continue,
+                };
+                let path = decl.module();
+                // Shards are compiled to wasm modules named after them, so
+                // their names must be unique.
+                let used = program.modules.iter().find(|module| module.path == path);
+                if let Some(Module {
+                    shard: Some(name),
+                    path: shard_path,
+                    ..
+                }) = used
+                {
+                    if *shards.entry(name).or_insert(shard_path) != shard_path {
+                        let message = format!("the shard `{}` is defined multiple times", name);
+                        self.error(decl.span.clone(), message);
+                    }
+                }
+                let names = match &decl.names {
+                    Some(names) => names,
+                    None => {
+                        let name = decl.path.last().unwrap();
+                        let namespace = &mut self.namespaces[index];
+                        if namespace.modules.insert(name.clone(), path).is_some() {
+                            let message = format!("the module `{}` is used multiple times", name);
+                            self.error(decl.span.clone(), message);
+                        }
+                        continue;
+                    }
+                };
+                for (name, span) in names {
+                    let linked = format!("{}::{}", path, name);
+                    let message = if !declared.contains(&linked) {
+                        format!("cannot find `{}` in module `{}`", name, path)
+                    } else if let Some(message) = self.local_error(&linked) {
+                        message
+                    } else if self.namespaces[index].items.contains_key(name) {
+                        format!("the name `{}` is defined multiple times", name)
+                    } else {
+                        self.namespaces[index].items.insert(name.clone(), linked);
+                        continue;
+                    };
+                    self.error(span.clone(), message);
+                }
+            }
+        }
+    }
+
+    // Checks a statement list and returns the type of its value, which is the
+    // value of the final expression statement if `used`, and `()` otherwise.
+    // `expected` is the hint for that final statement.
+    fn check_stmts(&mut self, stmts: &mut [Stmt], expected: Option<&Type>, used: bool) -> Type {
+        self.declare_fns(stmts);
+        let mut typ = Type::Unit;
+        let mut diverged = false;
+        let len = stmts.len();
+        for (i, stmt) in stmts.iter_mut().enumerate() {
+            let last = i + 1 == len;
+            typ = match &mut *stmt {
+                Stmt::Expr(expr) if !used || !last => {
+                    self.check_unused_expr(expr);
+                    Type::Unit
+                }
+                stmt => self.check_stmt(stmt, if last { expected } else { None }),
+            };
+            let value = match &*stmt {
+                Stmt::Expr(expr) => expr,
+                Stmt::Decl(decl) => &decl.expr,
+                Stmt::Assign(assign) => &assign.expr,
+                // Items do not run where they are written.
+                Stmt::Fn(_) => continue,
+            };
+            if diverged {
+                self.warning(value.span.clone(), String::from("unreachable statement"));
+                diverged = false;
+            } else if value.typ == Some(Type::Never) {
+                diverged = true;
+            }
+        }
+        typ
+    }
+
+    fn declare_fns(&mut self, stmts: &mut [Stmt]) {
+        for stmt in stmts.iter_mut() {
+            if let Stmt::Fn(decl) = stmt {
+                let typ = self.declare_fn(&decl.name, &decl.args, decl.ret.as_ref(), &decl.span);
+                decl.typ = Some(typ);
+            }
+        }
+    }
+
+    // Function items can be called before they are defined, so their
+    // signatures are declared before the rest of their scope is checked.
+    // Returns the function's type.
+    fn declare_fn(
+        &mut self,
+        name: &str,
+        args: &[Arg],
+        ret: Option<&TypeExpr>,
+        span: &Span,
+    ) -> Type {
+        let params = args.iter().map(|arg| self.check_type(&arg.typ)).collect();
+        let ret = match ret {
+            Some(ret) => self.check_type(ret),
+            None => Type::Unit,
+        };
+        let typ = Type::Fn(params, Box::new(ret));
+        let binding = Binding {
+            typ: typ.clone(),
+            mutable: false,
+            is_static_fn: true,
+        };
+        self.declare_item(name, binding, span);
+        typ
+    }
+
+    // Declares the functions of an `impl` block under their paths.
+    fn declare_impl(&mut self, block: &mut ImplDecl) {
+        let name = &block.name;
+        let linked = self.item_name(name).unwrap_or_default();
+        let typ = match self.type_decls.get(&linked) {
+            Some((TypeDecl::Struct(_) | TypeDecl::Enum(_), _)) => self.resolve_named(&linked),
+            Some((TypeDecl::Alias(..), _)) => {
+                let message = format!("expected struct or enum, found type alias `{}`", name);
+                self.error(block.span.clone(), message);
+                return;
+            }
+            None => {
+                let message = format!("cannot find struct or enum `{}`", name);
+                self.error(block.span.clone(), message);
+                return;
+            }
+        };
+        // The functions are linked under the path of the type.
+        block.name = linked;
+        let module = self.module;
+        for decl in block.fns.iter_mut() {
+            let path = format!("{}::{}", block.name, decl.name);
+            if let Type::Enum(en) = &typ {
+                if en.variant(&decl.name).is_some() {
+                    let message = format!("`{}` is already defined as a variant", path);
+                    self.error(decl.span.clone(), message);
+                }
+            }
+            let typ = self.declare_fn(&path, &decl.args, decl.ret.as_ref(), &decl.span);
+            decl.typ = Some(typ);
+            let is_method = decl.args.first().is_some_and(|arg| arg.name == "self");
+            self.assoc_fns.insert(path.clone(), is_method);
+            if decl.local {
+                let what = if is_method {
+                    "method"
+                } else {
+                    "associated function"
+                };
+                let local = (module, what, decl.span.clone());
+                self.local_items.insert(path.clone(), local);
+            }
+            self.fn_modules.insert(path, module);
+        }
+    }
+
+    // Declares an item, which unlike a `let` cannot shadow another item of
+    // the same scope.
+    fn declare_item(&mut self, name: &str, binding: Binding, span: &Span) {
+        if self.scopes.last().unwrap().contains_key(name) {
+            let message = format!("the name `{}` is defined multiple times", name);
+            self.error(span.clone(), message);
+        }
+        self.declare_binding(name, binding);
+    }
+
+    // The signature was declared along with the rest of the scope.
+    fn check_fn(&mut self, decl: &mut FnDecl) {
+        let (params, ret) = match decl.typ.clone() {
+            Some(Type::Fn(params, ret)) => (params, *ret),
+            _ => panic!("function item was not declared"),
+        };
+        self.check_fn_body(&decl.args, &params, Some(ret), &mut decl.body);
+    }
+
+    // Checks an expression whose value is discarded. Such an `if` or block
+    // has type `()`, so its branches need not agree.
+    fn check_unused_expr(&mut self, expr: &mut Expr) {
+        let typ = match &mut expr.kind {
+            ExprKind::If(cond, then, els) => {
+                self.check_if(cond, then, els.as_deref_mut(), None, false, &expr.span)
+            }
+            ExprKind::Block(stmts) => {
+                self.scopes.push(HashMap::new());
+                self.check_stmts(stmts, None, false);
+                self.scopes.pop();
+                Type::Unit
+            }
+            ExprKind::Match(scrutinee, arms) => self.check_match(scrutinee, arms, None, false),
+            _ => {
+                self.check_expr(expr, None);
+                return;
+            }
+        };
+        expr.typ = Some(typ);
+    }
+
+    fn check_if(
+        &mut self,
+        cond: &mut Expr,
+        then: &mut Expr,
+        els: Option<&mut Expr>,
+        expected: Option<&Type>,
+        used: bool,
+        span: &Span,
+    ) -> Type {
+        self.check_cond(cond);
+        if !used {
+            self.check_unused_expr(then);
+            return match els {
+                Some(els) => {
+                    self.check_unused_expr(els);
+                    match (&then.typ, &els.typ) {
+                        (Some(Type::Never), Some(Type::Never)) => Type::Never,
+                        _ => Type::Unit,
+                    }
+                }
+                None => Type::Unit,
+            };
+        }
+
+        let then_typ = self.check_expr(then, expected);
+        let els = match els {
+            Some(els) => els,
+            None => {
+                if !matches!(then_typ.unalias(), Type::Unit | Type::Never | Type::Error) {
+                    let message = format!(
+                        "`if` without `else` has type `()`, but its branch has type `{}`",
+                        then_typ
+                    );
+                    self.error(span.clone(), message);
+                    return Type::Error;
+                }
+                return Type::Unit;
+            }
+        };
+        let hint = match then_typ {
+            Type::Never | Type::Error => expected,
+            _ => Some(&then_typ),
+        };
+        let else_typ = self.check_expr(els, hint);
+        match (&then_typ, &else_typ) {
+            (Type::Error, _) | (_, Type::Error) => Type::Error,
+            _ => match join(&then_typ, &else_typ) {
+                Some(typ) => typ,
+                None => {
+                    let message = format!(
+                        "`if` and `else` have incompatible types: `{}` and `{}`",
+                        then_typ, else_typ
+                    );
+                    self.error(span.clone(), message);
+                    Type::Error
+                }
+            },
+        }
+    }
+
+    fn check_cond(&mut self, cond: &mut Expr) {
+        let typ = self.check_expr(cond, Some(&Type::Bool));
+        if typ != Type::Bool && typ != Type::Error && typ != Type::Never {
+            let message = format!("expected `bool`, found `{}`", typ);
+            self.error(cond.span.clone(), message);
+        }
+    }
+
+    // Checks a loop body, whose value is discarded, and returns the scope of
+    // the loop once done.
+    fn check_loop_body(
+        &mut self,
+        body: &mut Expr,
+        label: &Option<Label>,
+        keyword: &'static str,
+        expected: Option<&Type>,
+    ) -> LoopScope {
+        self.loops.push(LoopScope {
+            label: label.clone(),
+            keyword,
+            expected: expected.cloned(),
+            break_typ: None,
+        });
+        self.check_unused_expr(body);
+        self.loops.pop().unwrap()
+    }
+
+    // Finds the loop targeted by a `break` or `continue`.
+    fn find_loop(&mut self, label: &Option<Label>, keyword: &str, span: &Span) -> Option<usize> {
+        let index = match label {
+            Some(label) => self
+                .loops
+                .iter()
+                .rposition(|l| l.label.as_ref() == Some(label)),
+            None => self.loops.len().checked_sub(1),
+        };
+        if index.is_none() {
+            let message = match label {
+                Some(label) => format!("use of undeclared label `'{}`", label),
+                None => format!("`{}` outside of a loop", keyword),
+            };
+            self.error(span.clone(), message);
+        }
+        index
+    }
+
+    fn check_stmt(&mut self, stmt: &mut Stmt, expected: Option<&Type>) -> Type {
+        match stmt {
+            Stmt::Expr(expr) => self.check_expr(expr, expected),
+            Stmt::Decl(decl) => {
+                let declared = decl.typ.as_ref().map(|typ| self.check_type(typ));
+                let typ = self.check_expr(&mut decl.expr, declared.as_ref());
+                let typ = match declared {
+                    Some(declared) => {
+                        self.merge(Some(declared.clone()), typ, &decl.expr.span);
+                        declared
+                    }
+                    None => typ,
+                };
+                let binding = Binding {
+                    typ,
+                    mutable: decl.mutable,
+                    is_static_fn: !decl.mutable && matches!(decl.expr.kind, ExprKind::Fn(..)),
+                };
+                self.declare_binding(&decl.name, binding);
+                Type::Unit
+            }
+            Stmt::Assign(assign) => {
+                let target = self.check_place(&mut assign.target);
+                let hint = target.as_ref().map(|(typ, _)| typ.clone());
+                let typ = self.check_expr(&mut assign.expr, hint.as_ref());
+                let (target_typ, root) = match target {
+                    Some(target) => target,
+                    None => return Type::Unit,
+                };
+                let mutable = self.lookup(&root).is_some_and(|(_, b)| b.mutable);
+                let message = if !mutable {
+                    Some(format!("cannot assign to immutable binding `{}`", root))
+                } else if matches!(typ, Type::Error | Type::Never) || target_typ == Type::Error {
+                    None
+                } else {
+                    match assign.op {
+                        Some(op)
+                            if typ != target_typ || binop_type(op, &typ) != Some(typ.clone()) =>
+                        {
+                            Some(format!(
+                                "cannot apply `{}=` to `{}` and `{}`",
+                                op, target_typ, typ
+                            ))
+                        }
+                        None if typ != target_typ => {
+                            Some(format!("expected `{}`, found `{}`", target_typ, typ))
+                        }
+                        _ => None,
+                    }
+                };
+                if let Some(message) = message {
+                    self.error(assign.span.clone(), message);
+                }
+                Type::Unit
+            }
+            Stmt::Fn(decl) => {
+                self.check_fn(decl);
+                Type::Unit
+            }
+        }
+    }
+
+    // Checks `expr` and records its type in it. `expected` is a hint used to
+    // type unsuffixed literals; it is not enforced.
+    pub fn check_expr(&mut self, expr: &mut Expr, expected: Option<&Type>) -> Type {
+        let typ = match &mut expr.kind {
+            ExprKind::Block(stmts) => {
+                self.scopes.push(HashMap::new());
+                let typ = self.check_stmts(stmts, expected, true);
+                self.scopes.pop();
+                typ
+            }
+            ExprKind::BinOp(op, lexpr, rexpr) => {
+                // The operands of comparisons and logical operators need not
+                // have the type of the result.
+                let hint = match op {
+                    BinOp::Eq | BinOp::Ne | BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge => None,
+                    BinOp::And | BinOp::Or => Some(&Type::Bool),
+                    _ => expected,
+                };
+                // Let `1 + x` type the literal after `x`.
+                let (ltyp, rtyp) = if is_unsuffixed_literal(lexpr) {
+                    let rtyp = self.check_expr(rexpr, hint);
+                    (self.check_expr(lexpr, Some(&rtyp)), rtyp)
+                } else {
+                    let ltyp = self.check_expr(lexpr, hint);
+                    let rtyp = self.check_expr(rexpr, Some(&ltyp));
+                    (ltyp, rtyp)
+                };
+                match (&ltyp, &rtyp) {
+                    (Type::Error, _) | (_, Type::Error) => Type::Error,
+                    _ => match binop_type(*op, &ltyp) {
+                        Some(typ) if ltyp == rtyp => typ,
+                        _ => {
+                            let message =
+                                format!("cannot apply `{}` to `{}` and `{}`", op, ltyp, rtyp);
+                            self.error(expr.span.clone(), message);
+                            Type::Error
+                        }
+                    },
+                }
+            }
+            ExprKind::If(cond, then, els) => {
+                self.check_if(cond, then, els.as_deref_mut(), expected, true, &expr.span)
+            }
+            ExprKind::While(label, cond, body) => {
+                self.check_cond(cond);
+                self.check_loop_body(body, label, "while", None);
+                Type::Unit
+            }
+            // A `loop` that is never broken out of never produces a value.
+            ExprKind::Loop(label, body) => {
+                let scope = self.check_loop_body(body, label, "loop", expected);
+                scope.break_typ.unwrap_or(Type::Never)
+            }
+            ExprKind::For(label, var, lo, hi, body) => {
+                let lo_typ = self.check_expr(lo, None);
+                let hi_typ = self.check_expr(hi, Some(&lo_typ));
+                let typ = match (lo_typ.unalias(), &hi_typ) {
+                    (Type::Error, _) | (_, Type::Error) => Type::Error,
+                    (Type::Int(_), _) if lo_typ == hi_typ => lo_typ.clone(),
+                    _ => {
+                        let message = format!(
+                            "cannot iterate over a range from `{}` to `{}`",
+                            lo_typ, hi_typ
+                        );
+                        self.error(expr.span.clone(), message);
+                        Type::Error
+                    }
+                };
+                self.scopes.push(HashMap::new());
+                self.declare(var, typ);
+                self.check_loop_body(body, label, "for", None);
+                self.scopes.pop();
+                Type::Unit
+            }
+            ExprKind::Break(label, value) => {
+                let index = self.find_loop(label, "break", &expr.span);
+                let hint = index.and_then(|i| {
+                    let scope = &self.loops[i];
+                    scope.break_typ.clone().or_else(|| scope.expected.clone())
+                });
+                let typ = match value {
+                    Some(value) => self.check_expr(value, hint.as_ref()),
+                    None => Type::Unit,
+                };
+                if let Some(i) = index {
+                    let keyword = self.loops[i].keyword;
+                    let prev = self.loops[i].break_typ.clone();
+                    if value.is_some() && keyword != "loop" {
+                        let message = format!("`break` with value from a `{}` loop", keyword);
+                        self.error(expr.span.clone(), message);
+                    } else {
+                        let joined = self.merge(prev, typ, &expr.span);
+                        self.loops[i].break_typ = Some(joined);
+                    }
+                }
+                Type::Never
+            }
+            ExprKind::Return(value) => {
+                let hint = self.ret.clone();
+                let typ = match value {
+                    Some(value) => self.check_expr(value, hint.as_ref()),
+                    None => Type::Unit,
+                };
+                let prev = self.ret.take();
+                self.ret = Some(self.merge(prev, typ, &expr.span));
+                Type::Never
+            }
+            ExprKind::Continue(label) => {
+                self.find_loop(label, "continue", &expr.span);
+                Type::Never
+            }
+            ExprKind::UnOp(op, operand) => {
+                let typ = match (*op, &mut operand.kind) {
+                    // `-128i8` is in range although `128i8` is not.
+                    (UnOp::Neg, ExprKind::Literal(lit @ Literal::Int(..))) => {
+                        let typ = self.check_literal(lit, expected, true, &operand.span);
+                        operand.typ = Some(typ.clone());
+                        typ
+                    }
+                    _ => self.check_expr(operand, expected),
+                };
+                match (*op, typ.unalias()) {
+                    (_, Type::Error) => Type::Error,
+                    (UnOp::Neg, Type::Int(t)) if t.is_signed() => typ.clone(),
+                    (UnOp::Neg, Type::Float(_)) => typ.clone(),
+                    (UnOp::Not, Type::Int(_) | Type::Bool) => typ.clone(),
+                    _ => {
+                        let message = format!("cannot apply unary `{}` to `{}`", op, typ);
+                        self.error(expr.span.clone(), message);
+                        Type::Error
+                    }
+                }
+            }
+            ExprKind::Literal(lit) => self.check_literal(lit, expected, false, &expr.span),
+            ExprKind::Fn(args, declared, body) => {
+                let params: Vec<Type> = args.iter().map(|arg| self.check_type(&arg.typ)).collect();
+                let declared = declared.as_ref().map(|typ| self.check_type(typ));
+                let ret = self.check_fn_body(args, &params, declared, body);
+                Type::Fn(params, Box::new(ret))
+            }
+            ExprKind::Call(callee, args) => {
+                // A method call `base.name(args)` becomes a call of the path
+                // of the method, with `base` as its first argument.
+                let mut receiver = false;
+                let callee_typ = match &mut callee.kind {
+                    ExprKind::Path(..) => self.check_path(callee, true),
+                    ExprKind::Field(base, name) => {
+                        let base_typ = self.check_expr(base, None);
+                        match self.find_method(&base_typ, name, &callee.span) {
+                            Some(Ok(path)) => {
+                                receiver = true;
+                                let base = std::mem::replace(
+                                    &mut **base,
+                                    Expr::new(ExprKind::Error, 0..0),
+                                );
+                                args.insert(0, base);
+                                callee.kind = ExprKind::Var(path);
+                                self.check_expr(callee, None)
+                            }
+                            Some(Err(())) => {
+                                callee.typ = Some(Type::Error);
+                                Type::Error
+                            }
+                            None => {
+                                let typ = self.field_type(&base_typ, name, &callee.span);
+                                callee.typ = Some(typ.unalias().clone());
+                                typ
+                            }
+                        }
+                    }
+                    _ => self.check_expr(callee, None),
+                };
+                match callee_typ.unalias().clone() {
+                    Type::Fn(params, ret) if params.len() == args.len() => {
+                        // The receiver has already been checked.
+                        let skip = receiver as usize;
+                        for (arg, param) in args.iter_mut().zip(params.iter()).skip(skip) {
+                            let typ = self.check_expr(arg, Some(param));
+                            if typ != *param && typ != Type::Error && *param != Type::Error {
+                                let message = format!("expected `{}`, found `{}`", param, typ);
+                                self.error(arg.span.clone(), message);
+                            }
+                        }
+                        *ret
+                    }
+                    _ => {
+                        let skip = receiver as usize;
+                        for arg in args.iter_mut().skip(skip) {
+                            self.check_expr(arg, None);
+                        }
+                        match callee_typ.unalias() {
+                            Type::Fn(params, _) => {
+                                let (params, args) = (params.len() - skip, args.len() - skip);
+                                let message = format!(
+                                    "this {} takes {} argument{} but {} {} supplied",
+                                    if receiver { "method" } else { "function" },
+                                    params,
+                                    if params == 1 { "" } else { "s" },
+                                    args,
+                                    if args == 1 { "was" } else { "were" }
+                                );
+                                self.error(expr.span.clone(), message);
+                            }
+                            Type::Error => (),
+                            typ => {
+                                let message = format!("expected function, found `{}`", typ);
+                                self.error(callee.span.clone(), message);
+                            }
+                        }
+                        Type::Error
+                    }
+                }
+            }
+            ExprKind::Var(name) => match self.resolve(name, &expr.span) {
+                Some((typ, _)) => typ,
+                None => Type::Error,
+            },
+            ExprKind::Struct(name, variant, fields) => {
+                self.struct_path(name, variant);
+                self.check_struct_expr(name, variant.as_deref(), fields, &expr.span)
+            }
+            ExprKind::Tuple(exprs) => {
+                let hints = match expected.map(Type::unalias) {
+                    Some(Type::Tuple(st)) => st.fields.iter().map(|(_, t)| t.clone()).collect(),
+                    _ => Vec::new(),
+                };
+                let mut types = Vec::new();
+                for (i, expr) in exprs.iter_mut().enumerate() {
+                    types.push(self.check_expr(expr, hints.get(i)));
+                }
+                match types.contains(&Type::Error) {
+                    true => Type::Error,
+                    false => Type::Tuple(Rc::new(StructType::tuple(types))),
+                }
+            }
+            ExprKind::Path(..) => self.check_path(expr, false),
+            ExprKind::Match(scrutinee, arms) => self.check_match(scrutinee, arms, expected, true),
+            ExprKind::Field(base, name) => {
+                let base_typ = self.check_expr(base, None);
+                self.field_type(&base_typ, name, &expr.span)
+            }
+            ExprKind::Error => Type::Error,
+        };
+        // Aliases only matter to diagnostics.
+        expr.typ = Some(typ.unalias().clone());
+        typ
+    }
+
+    // `module::Name { ... }` is parsed like a variant, but names a struct of
+    // the module.
+    fn struct_path(&self, name: &mut Ident, variant: &mut Option<Ident>) {
+        if variant.is_some() && self.module_name(name).is_some() {
+            *name = format!("{}::{}", name, variant.take().unwrap());
+        }
+    }
+
+    fn check_struct_expr(
+        &mut self,
+        name: &str,
+        variant: Option<&str>,
+        fields: &mut [(Ident, Expr)],
+        span: &Span,
+    ) -> Type {
+        let variant = match variant {
+            Some(variant) => variant,
+            None => {
+                let st = match self.named_type(name, span) {
+                    Some(Type::Struct(st)) => st,
+                    other => {
+                        match other {
+                            Some(Type::Error) => (),
+                            Some(typ) => {
+                                let message = format!("expected struct, found `{}`", typ);
+                                self.error(span.clone(), message);
+                            }
+                            None => {
+                                let message = format!("cannot find struct `{}`", name);
+                                self.error(span.clone(), message);
+                            }
+                        }
+                        self.check_field_exprs(fields);
+                        return Type::Error;
+                    }
+                };
+                self.check_fields(&format!("struct `{}`", name), name, &st, fields, span);
+                return Type::Struct(st);
+            }
+        };
+        let (en, index) = match self.lookup_variant(name, variant, span) {
+            Some(found) => found,
+            None => {
+                self.check_field_exprs(fields);
+                return Type::Error;
+            }
+        };
+        let found = &en.variants[index];
+        let path = format!("{}::{}", name, variant);
+        if found.kind != VariantKind::Struct {
+            let message = format!(
+                "expected struct variant, found {} variant `{}`",
+                kind_name(found.kind),
+                path
+            );
+            self.error(span.clone(), message);
+            self.check_field_exprs(fields);
+            return Type::Error;
+        }
+        let what = format!("variant `{}`", path);
+        self.check_fields(&what, &path, &found.payload, fields, span);
+        Type::Enum(en)
+    }
+
+    fn check_field_exprs(&mut self, fields: &mut [(Ident, Expr)]) {
+        for (_, expr) in fields.iter_mut() {
+            self.check_expr(expr, None);
+        }
+    }
+
+    // Checks the fields of a literal of the struct or variant `what`, which
+    // is written `name`.
+    fn check_fields(
+        &mut self,
+        what: &str,
+        name: &str,
+        st: &StructType,
+        fields: &mut [(Ident, Expr)],
+        span: &Span,
+    ) {
+        let mut seen: Vec<&str> = Vec::new();
+        for (field, expr) in fields.iter_mut() {
+            let declared = st.field(field).map(|(_, typ)| typ.clone());
+            let typ = self.check_expr(expr, declared.as_ref());
+            if seen.contains(&field.as_str()) {
+                let message = format!("field `{}` specified more than once", field);
+                self.error(expr.span.clone(), message);
+                continue;
+            }
+            seen.push(field);
+            match declared {
+                Some(declared) => {
+                    self.merge(Some(declared), typ, &expr.span);
+                }
+                None => {
+                    let message = format!("{} has no field named `{}`", what, field);
+                    self.error(expr.span.clone(), message);
+                }
+            }
+        }
+        for (field, _) in &st.fields {
+            if !seen.contains(&field.as_str()) {
+                let message = format!("missing field `{}` in initializer of `{}`", field, name);
+                self.error(span.clone(), message);
+            }
+        }
+    }
+
+    // Finds the variant `variant` of the enum `name`, and returns the enum
+    // and the index of the variant.
+    fn lookup_variant(
+        &mut self,
+        name: &str,
+        variant: &str,
+        span: &Span,
+    ) -> Option<(Rc<EnumType>, usize)> {
+        let message = match self.named_type(name, span) {
+            Some(Type::Enum(en)) => match en.variant(variant) {
+                Some((index, _)) => return Some((en, index as usize)),
+                None => format!("no variant named `{}` in enum `{}`", variant, name),
+            },
+            Some(Type::Error) => return None,
+            Some(_) => format!("expected enum, found `{}`", name),
+            None => format!("cannot find enum `{}`", name),
+        };
+        self.error(span.clone(), message);
+        None
+    }
+
+    // Unsuffixed number literals take the expected type if it fits, and the
+    // inferred type is written back as their suffix. An integer literal must
+    // be in range for its type, where a `negated` one may be the minimum.
+    fn check_literal(
+        &mut self,
+        lit: &mut Literal,
+        expected: Option<&Type>,
+        negated: bool,
+        span: &Span,
+    ) -> Type {
+        match lit {
+            Literal::Int(value, suffix) => {
+                let t = match (*suffix, expected.map(Type::unalias)) {
+                    (Some(t), _) | (None, Some(&Type::Int(t))) => t,
+                    _ => IntTy::I64,
+                };
+                *suffix = Some(t);
+                let max = match t.is_signed() && negated {
+                    true => t.max() + 1,
+                    false => t.max(),
+                };
+                if *value > max {
+                    let message = format!("literal out of range for `{}`", Type::Int(t));
+                    self.error(span.clone(), message);
+                }
+                Type::Int(t)
+            }
+            Literal::Float(_, suffix) => {
+                let t = match (*suffix, expected.map(Type::unalias)) {
+                    (Some(t), _) | (None, Some(&Type::Float(t))) => t,
+                    _ => FloatTy::F64,
+                };
+                *suffix = Some(t);
+                Type::Float(t)
+            }
+            Literal::String(_) => Type::String,
+            Literal::Bool(_) => Type::Bool,
+        }
+    }
+
+    // Checks `Type::name`, a variant or an associated function. A path to a
+    // function is replaced by a variable of that name.
+    fn check_path(&mut self, expr: &mut Expr, callee: bool) -> Type {
+        let (name, item) = match &expr.kind {
+            ExprKind::Path(name, item) => (name.clone(), item.clone()),
+            _ => unreachable!("not a path"),
+        };
+        // An item of a module used as `module::item`.
+        if let Some(module) = self.module_name(&name) {
+            let path = format!("{}::{}", module, item);
+            if self.scopes[0].contains_key(&path) {
+                expr.kind = ExprKind::Var(path);
+                return self.check_expr(expr, None);
+            }
+            let message = format!("cannot find value `{}` in module `{}`", item, module);
+            self.error(expr.span.clone(), message);
+            expr.typ = Some(Type::Error);
+            return Type::Error;
+        }
+        let owner = self.item_name(&name).unwrap_or_else(|| name.clone());
+        let path = format!("{}::{}", owner, item);
+        if self.assoc_fns.contains_key(&path) {
+            expr.kind = ExprKind::Var(path);
+            return self.check_expr(expr, None);
+        }
+        let typ = match self.named_type(&name, &expr.span) {
+            Some(Type::Struct(_)) => {
+                let message = format!("no function named `{}` in `{}`", item, name);
+                self.error(expr.span.clone(), message);
+                Type::Error
+            }
+            _ => self.path_type(&name, &item, &expr.span, callee),
+        };
+        expr.typ = Some(typ.clone());
+        typ
+    }
+
+    // The path of the method `name` called on a value of type `typ`, or
+    // `None` if `name` is a field. Reports a call of an associated function
+    // that is not a method, or of a method that does not exist.
+    fn find_method(&mut self, typ: &Type, name: &str, span: &Span) -> Option<Result<Ident, ()>> {
+        let (owner, has_field) = match typ.unalias() {
+            Type::Struct(st) => (&st.name, st.field(name).is_some()),
+            Type::Enum(en) => (&en.name, false),
+            _ => return None,
+        };
+        let path = format!("{}::{}", owner, name);
+        match self.assoc_fns.get(&path).copied() {
+            Some(true) if !self.use_item(&path, span) => Some(Err(())),
+            Some(true) => Some(Ok(path)),
+            Some(false) => {
+                let message = format!("`{}` is an associated function, not a method", path);
+                self.error(span.clone(), message);
+                Some(Err(()))
+            }
+            None if !has_field => {
+                let message = format!("no method named `{}` on type `{}`", name, typ);
+                self.error(span.clone(), message);
+                Some(Err(()))
+            }
+            None => None,
+        }
+    }
+
+    // The type of `Enum::Variant` used as a value, or as the callee of a
+    // call if `callee`. Tuple variants are called like functions.
+    fn path_type(&mut self, name: &str, variant: &str, span: &Span, callee: bool) -> Type {
+        let (en, index) = match self.lookup_variant(name, variant, span) {
+            Some(found) => found,
+            None => return Type::Error,
+        };
+        let found = &en.variants[index];
+        match found.kind {
+            VariantKind::Unit => Type::Enum(en.clone()),
+            VariantKind::Tuple if callee => {
+                let params = found.payload.fields.iter().map(|(_, t)| t.clone());
+                Type::Fn(params.collect(), Box::new(Type::Enum(en.clone())))
+            }
+            kind => {
+                let message = format!(
+                    "expected value, found {} variant `{}::{}`",
+                    kind_name(kind),
+                    name,
+                    variant
+                );
+                self.error(span.clone(), message);
+                Type::Error
+            }
+        }
+    }
+
+    // Checks a `match`. Like an `if`, a `match` whose value is unused has
+    // type `()` and its arms need not agree.
+    fn check_match(
+        &mut self,
+        scrutinee: &mut Expr,
+        arms: &mut [Arm],
+        expected: Option<&Type>,
+        used: bool,
+    ) -> Type {
+        let typ = self.check_expr(scrutinee, None);
+        // The patterns of the unguarded arms so far.
+        let mut rows = Vec::new();
+        let mut result: Option<Type> = None;
+        for arm in arms.iter_mut() {
+            self.scopes.push(HashMap::new());
+            // A pattern with errors is left out of the analysis, so it does
+            // not make the arms after it look unreachable.
+            let reported = self.errors.len();
+            let pat = self.check_pattern(&mut arm.pattern, &typ);
+            let valid = typ != Type::Error && self.errors.len() == reported;
+            if valid && !exhaustiveness::is_useful(&rows, &pat, &typ) {
+                let message = String::from("unreachable pattern");
+                self.warning(arm.pattern.span.clone(), message);
+            }
+            match &mut arm.guard {
+                Some(guard) => self.check_cond(guard),
+                None if valid => rows.push(pat),
+                None => (),
+            }
+            let body_typ = if used {
+                let hint = match &result {
+                    Some(Type::Never | Type::Error) | None => expected.cloned(),
+                    Some(typ) => Some(typ.clone()),
+                };
+                self.check_expr(&mut arm.body, hint.as_ref())
+            } else {
+                self.check_unused_expr(&mut arm.body);
+                match arm.body.typ {
+                    Some(Type::Never) => Type::Never,
+                    _ => Type::Unit,
+                }
+            };
+            self.scopes.pop();
+            result = Some(match result {
+                None => body_typ,
+                Some(Type::Error) => Type::Error,
+                Some(_) if body_typ == Type::Error => Type::Error,
+                Some(prev) => match join(&prev, &body_typ) {
+                    Some(joined) => joined,
+                    None => {
+                        let message = format!(
+                            "`match` arms have incompatible types: `{}` and `{}`",
+                            prev, body_typ
+                        );
+                        self.error(arm.body.span.clone(), message);
+                        Type::Error
+                    }
+                },
+            });
+        }
+        if typ != Type::Error {
+            if let Some(pattern) = exhaustiveness::missing(&rows, &typ) {
+                let message = format!("non-exhaustive patterns: `{}` not covered", pattern);
+                self.error(scrutinee.span.clone(), message);
+            }
+        }
+        // A `match` without arms matches a value that cannot exist.
+        result.unwrap_or(Type::Never)
+    }
+
+    // Checks a pattern against the type of the value it matches, and
+    // declares its bindings in the current scope.
+    fn check_pattern(&mut self, pattern: &mut Pattern, typ: &Type) -> Pat {
+        let span = pattern.span.clone();
+        match &mut pattern.kind {
+            PatternKind::Wildcard => Pat::Wild,
+            PatternKind::Binding(name) => {
+                if self.scopes.last().unwrap().contains_key(name.as_str()) {
+                    let message = format!(
+                        "identifier `{}` is bound more than once in the same pattern",
+                        name
+                    );
+                    self.error(span, message);
+                }
+                self.declare(name, typ.clone());
+                Pat::Wild
+            }
+            PatternKind::Literal(expr) => {
+                let lit_typ = self.check_expr(expr, Some(typ));
+                let message = match (&lit_typ, typ) {
+                    (Type::Error, _) | (_, Type::Error) => return Pat::Wild,
+                    (Type::Int(_) | Type::Bool, _) if lit_typ == *typ => {
+                        return Pat::Ctor(literal_ctor(expr), Vec::new());
+                    }
+                    (Type::Int(_) | Type::Bool, _) => {
+                        format!("expected `{}`, found `{}`", typ, lit_typ)
+                    }
+                    _ => String::from("only integer and `bool` literals can be used in patterns"),
+                };
+                self.error(span, message);
+                Pat::Wild
+            }
+            PatternKind::Path(name, variant) => {
+                let found =
+                    self.check_variant_pattern(name, variant, VariantKind::Unit, typ, &span);
+                match found {
+                    Some((_, index, true)) => Pat::Ctor(Ctor::Variant(index), Vec::new()),
+                    _ => Pat::Wild,
+                }
+            }
+            PatternKind::Tuple(name, variant, patterns) => {
+                let found =
+                    self.check_variant_pattern(name, variant, VariantKind::Tuple, typ, &span);
+                let (en, index, matches) = match found {
+                    Some(found) => found,
+                    None => {
+                        for pattern in patterns.iter_mut() {
+                            self.check_pattern(pattern, &Type::Error);
+                        }
+                        return Pat::Wild;
+                    }
+                };
+                let fields = &en.variants[index].payload.fields;
+                if patterns.len() != fields.len() {
+                    let message = format!(
+                        "this pattern has {} field{}, but the corresponding tuple variant has {} field{}",
+                        patterns.len(),
+                        if patterns.len() == 1 { "" } else { "s" },
+                        fields.len(),
+                        if fields.len() == 1 { "" } else { "s" }
+                    );
+                    self.error(span, message);
+                }
+                let mut args = vec![Pat::Wild; fields.len()];
+                for (i, pattern) in patterns.iter_mut().enumerate() {
+                    let field_typ = fields.get(i).map_or(Type::Error, |(_, t)| t.clone());
+                    let arg = self.check_pattern(pattern, &field_typ);
+                    if i < args.len() {
+                        args[i] = arg;
+                    }
+                }
+                match matches && patterns.len() == fields.len() {
+                    true => Pat::Ctor(Ctor::Variant(index), args),
+                    false => Pat::Wild,
+                }
+            }
+            PatternKind::Struct(name, variant, fields, rest) => {
+                self.struct_path(name, variant);
+                let (payload, ctor, what, matches) = match variant {
+                    Some(variant) => {
+                        let kind = VariantKind::Struct;
+                        match self.check_variant_pattern(name, variant, kind, typ, &span) {
+                            Some((en, index, matches)) => {
+                                let what = format!("variant `{}::{}`", name, variant);
+                                let payload = en.variants[index].payload.fields.clone();
+                                (Some(payload), Ctor::Variant(index), what, matches)
+                            }
+                            None => (None, Ctor::Single, String::new(), false),
+                        }
+                    }
+                    None => match self.named_type(name, &span) {
+                        Some(Type::Struct(st)) => {
+                            let matches =
+                                self.check_pattern_type(&Type::Struct(st.clone()), typ, &span);
+                            let what = format!("struct `{}`", name);
+                            (Some(st.fields.clone()), Ctor::Single, what, matches)
+                        }
+                        other => {
+                            match other {
+                                Some(Type::Error) => (),
+                                Some(other) => {
+                                    let message = format!("expected struct, found `{}`", other);
+                                    self.error(span.clone(), message);
+                                }
+                                None => {
+                                    let message = format!("cannot find struct `{}`", name);
+                                    self.error(span.clone(), message);
+                                }
+                            }
+                            (None, Ctor::Single, String::new(), false)
+                        }
+                    },
+                };
+                let payload = match payload {
+                    Some(payload) => payload,
+                    None => {
+                        for (_, pattern) in fields.iter_mut() {
+                            self.check_pattern(pattern, &Type::Error);
+                        }
+                        return Pat::Wild;
+                    }
+                };
+                let mut args = vec![Pat::Wild; payload.len()];
+                let mut seen: Vec<&str> = Vec::new();
+                for (field, pattern) in fields.iter_mut() {
+                    let index = payload.iter().position(|(f, _)| f == field);
+                    let field_typ = index.map_or(Type::Error, |i| payload[i].1.clone());
+                    let arg = self.check_pattern(pattern, &field_typ);
+                    if seen.contains(&field.as_str()) {
+                        let message =
+                            format!("field `{}` bound multiple times in the pattern", field);
+                        self.error(pattern.span.clone(), message);
+                        continue;
+                    }
+                    seen.push(field);
+                    match index {
+                        Some(index) => args[index] = arg,
+                        None => {
+                            let message = format!("{} has no field named `{}`", what, field);
+                            self.error(pattern.span.clone(), message);
+                        }
+                    }
+                }
+                if !*rest {
+                    for (field, _) in &payload {
+                        if !seen.contains(&field.as_str()) {
+                            let message = format!("pattern does not mention field `{}`", field);
+                            self.error(span.clone(), message);
+                        }
+                    }
+                }
+                match matches {
+                    true => Pat::Ctor(ctor, args),
+                    false => Pat::Wild,
+                }
+            }
+        }
+    }
+
+    // Resolves the variant named by a pattern, which should be of `kind`.
+    // Returns the enum, the index of the variant and whether the pattern
+    // fits the type `typ` of the matched value.
+    fn check_variant_pattern(
+        &mut self,
+        name: &str,
+        variant: &str,
+        kind: VariantKind,
+        typ: &Type,
+        span: &Span,
+    ) -> Option<(Rc<EnumType>, usize, bool)> {
+        let (en, index) = self.lookup_variant(name, variant, span)?;
+        let found = en.variants[index].kind;
+        if found != kind {
+            let message = format!(
+                "expected {} variant, found {} variant `{}::{}`",
+                kind_name(kind),
+                kind_name(found),
+                name,
+                variant
+            );
+            self.error(span.clone(), message);
+            return None;
+        }
+        let matches = self.check_pattern_type(&Type::Enum(en.clone()), typ, span);
+        Some((en, index, matches))
+    }
+
+    // Reports a pattern of type `found` that cannot match values of type
+    // `typ`.
+    fn check_pattern_type(&mut self, found: &Type, typ: &Type, span: &Span) -> bool {
+        match typ {
+            Type::Error => false,
+            _ if typ == found => true,
+            _ => {
+                let message = format!("expected `{}`, found `{}`", typ, found);
+                self.error(span.clone(), message);
+                false
+            }
+        }
+    }
+
+    fn field_type(&mut self, base: &Type, name: &str, span: &Span) -> Type {
+        match base.unalias() {
+            Type::Error => Type::Error,
+            Type::Struct(st) | Type::Tuple(st) if st.field(name).is_some() => {
+                st.field(name).unwrap().1.clone()
+            }
+            _ => {
+                self.error(
+                    span.clone(),
+                    format!("no field `{}` on type `{}`", name, base),
+                );
+                Type::Error
+            }
+        }
+    }
+
+    // Checks the target of an assignment, a variable or a field of one.
+    // Returns its type and the name of the variable.
+    fn check_place(&mut self, place: &mut Expr) -> Option<(Type, Ident)> {
+        let (typ, root) = match &mut place.kind {
+            ExprKind::Var(name) => {
+                let (typ, _) = self.resolve(name, &place.span)?;
+                (typ, name.clone())
+            }
+            ExprKind::Field(base, name) => {
+                let (base_typ, root) = self.check_place(base)?;
+                match self.field_type(&base_typ, name, &place.span) {
+                    Type::Error => return None,
+                    typ => (typ, root),
+                }
+            }
+            _ => unreachable!("assignment target is not a place"),
+        };
+        place.typ = Some(typ.unalias().clone());
+        Some((typ, root))
+    }
+
+    // Checks the body of a function in a scope of its own and returns the
+    // function's return type. A declared return type is what every `return`
+    // and the body itself are checked against.
+    fn check_fn_body(
+        &mut self,
+        args: &[Arg],
+        params: &[Type],
+        declared: Option<Type>,
+        body: &mut Expr,
+    ) -> Type {
+        let outer_fn_scope = self.fn_scope;
+        let outer_loops = std::mem::take(&mut self.loops);
+        self.fn_scope = self.scopes.len();
+        self.scopes.push(HashMap::new());
+        for (arg, typ) in args.iter().zip(params) {
+            self.declare(&arg.name, typ.clone());
+        }
+        let outer_ret = std::mem::replace(&mut self.ret, declared.clone());
+        let body_typ = self.check_expr(body, declared.as_ref());
+        let ret = self.return_type(body_typ, &body.span);
+        let ret = declared.unwrap_or(ret);
+        self.ret = outer_ret;
+        self.scopes.pop();
+        self.fn_scope = outer_fn_scope;
+        self.loops = outer_loops;
+        ret
+    }
+
+    fn check_type(&mut self, typ: &TypeExpr) -> Type {
+        match &typ.kind {
+            TypeExprKind::Name(name, args) => {
+                let linked = self.item_name(name).unwrap_or_default();
+                let decl = self.type_decls.get(&linked).map(|(decl, _)| decl);
+                let is_alias = matches!(decl, Some(TypeDecl::Alias(..)));
+                let is_declared = decl.is_some();
+                let found = if let Some(param) = self.type_params.get(name) {
+                    param.clone()
+                } else if let Some(found) = Type::from_name(name) {
+                    found
+                } else if is_declared && !self.use_item(&linked, &typ.span) {
+                    return Type::Error;
+                } else if is_alias {
+                    let args = args.iter().map(|arg| self.check_type(arg)).collect();
+                    return self.expand_alias(&linked, args, &typ.span);
+                } else if is_declared {
+                    self.resolve_named(&linked)
+                } else {
+                    self.error(typ.span.clone(), format!("cannot find type `{}`", name));
+                    return Type::Error;
+                };
+                if !args.is_empty() {
+                    let message = format!("type `{}` takes no generic arguments", name);
+                    self.error(typ.span.clone(), message);
+                }
+                found
+            }
+            TypeExprKind::Unit => Type::Unit,
+            TypeExprKind::Tuple(types) => {
+                let types = types.iter().map(|typ| self.check_type(typ)).collect();
+                Type::Tuple(Rc::new(StructType::tuple(types)))
+            }
+            TypeExprKind::Fn(params, ret) => {
+                let params = params.iter().map(|param| self.check_type(param)).collect();
+                let ret = match ret {
+                    Some(ret) => self.check_type(ret),
+                    None => Type::Unit,
+                };
+                Type::Fn(params, Box::new(ret))
+            }
+        }
+    }
+
+    // Checks the definition of a type alias, with its generic parameters
+    // standing for any type.
+    fn check_alias(&mut self, decl: &TypeAlias) {
+        for (i, param) in decl.params.iter().enumerate() {
+            if decl.params[..i].contains(param) {
+                let message = format!(
+                    "the name `{}` is already used for a generic parameter",
+                    param
+                );
+                self.error(decl.span.clone(), message);
+            }
+        }
+        let args = vec![Type::Error; decl.params.len()];
+        self.expand_alias(&decl.name, args, &decl.span);
+    }
+
+    // The type written as the alias `name` with the generic arguments
+    // `args`, at `span`.
+    fn expand_alias(&mut self, name: &str, args: Vec<Type>, span: &Span) -> Type {
+        let (params, target, decl_span) = match &self.type_decls[name] {
+            (TypeDecl::Alias(params, target), decl_span) => {
+                (params.clone(), target.clone(), decl_span.clone())
+            }
+            _ => unreachable!("`{}` is not a type alias", name),
+        };
+        if args.len() != params.len() {
+            let message = format!(
+                "type alias `{}` takes {} generic argument{} but {} {} supplied",
+                name,
+                params.len(),
+                if params.len() == 1 { "" } else { "s" },
+                args.len(),
+                if args.len() == 1 { "was" } else { "were" }
+            );
+            self.error(span.clone(), message);
+            return Type::Error;
+        }
+        if self.resolving.iter().any(|n| n == name) {
+            let message = format!("cycle detected when expanding type alias `{}`", name);
+            self.error(decl_span, message);
+            return Type::Error;
+        }
+        self.resolving.push(name.to_string());
+        let bound = params.into_iter().zip(args.iter().cloned()).collect();
+        let outer = std::mem::replace(&mut self.type_params, bound);
+        // The alias is expanded in the module that declares it.
+        let declared_in = self.module_of(name);
+        let module = std::mem::replace(&mut self.module, declared_in);
+        let typ = self.check_type(&target);
+        self.module = module;
+        self.type_params = outer;
+        self.resolving.pop();
+        match typ {
+            Type::Error => Type::Error,
+            typ => Type::Alias(Rc::new(AliasType {
+                name: name.to_string(),
+                args,
+                typ,
+            })),
+        }
+    }
+
+    // Resolves the field types of the declared struct or enum `name`, once.
+    fn resolve_named(&mut self, name: &str) -> Type {
+        if let Some(typ) = self.named_types.get(name) {
+            return typ.clone();
+        }
+        let (decl, span) = self.type_decls[name].clone();
+        if self.resolving.iter().any(|n| n == name) {
+            let kind = match decl {
+                TypeDecl::Struct(_) => "struct",
+                TypeDecl::Enum(_) => "enum",
+                TypeDecl::Alias(..) => unreachable!("aliases are expanded, not resolved"),
+            };
+            let message = format!("recursive {} `{}` has infinite size", kind, name);
+            self.error(span, message);
+            self.named_types.insert(name.to_string(), Type::Error);
+            return Type::Error;
+        }
+        self.resolving.push(name.to_string());
+        let declared_in = self.module_of(name);
+        let module = std::mem::replace(&mut self.module, declared_in);
+        let typ = match decl {
+            TypeDecl::Struct(fields) => Type::Struct(Rc::new(StructType {
+                name: name.to_string(),
+                fields: self.resolve_fields(&fields),
+            })),
+            TypeDecl::Enum(variants) => {
+                let mut resolved: Vec<VariantType> = Vec::new();
+                for variant in &variants {
+                    let (kind, fields) = match &variant.fields {
+                        VariantFields::Unit => (VariantKind::Unit, Vec::new()),
+                        VariantFields::Tuple(types) => {
+                            let fields = types
+                                .iter()
+                                .enumerate()
+                                .map(|(i, typ)| (i.to_string(), self.check_type(typ)));
+                            (VariantKind::Tuple, fields.collect())
+                        }
+                        VariantFields::Struct(fields) => {
+                            (VariantKind::Struct, self.resolve_fields(fields))
+                        }
+                    };
+                    if resolved.iter().any(|v| v.name == variant.name) {
+                        let message = format!("variant `{}` is already declared", variant.name);
+                        self.error(variant.span.clone(), message);
+                        continue;
+                    }
+                    resolved.push(VariantType {
+                        name: variant.name.clone(),
+                        kind,
+                        payload: StructType {
+                            name: format!("{}::{}", name, variant.name),
+                            fields,
+                        },
+                    });
+                }
+                Type::Enum(Rc::new(EnumType {
+                    name: name.to_string(),
+                    variants: resolved,
+                }))
+            }
+            TypeDecl::Alias(..) => unreachable!("aliases are expanded, not resolved"),
+        };
+        self.module = module;
+        self.resolving.pop();
+        // A cycle through this type has already been reported.
+        if let Some(typ) = self.named_types.get(name) {
+            return typ.clone();
+        }
+        self.named_types.insert(name.to_string(), typ.clone());
+        typ
+    }
+
+    fn resolve_fields(&mut self, fields: &[Field]) -> Vec<(Ident, Type)> {
+        let mut resolved: Vec<(Ident, Type)> = Vec::new();
+        for field in fields {
+            let typ = self.check_type(&field.typ);
+            if resolved.iter().any(|(n, _)| *n == field.name) {
+                let message = format!("field `{}` is already declared", field.name);
+                self.error(field.span.clone(), message);
+                continue;
+            }
+            resolved.push((field.name.clone(), typ));
+        }
+        resolved
+    }
+}
+
+// Visits the shards the last shard of `stack` imports from, depth first, and
+// reports an import of a shard of the stack.
+fn visit_shard<'a>(
+    edges: &HashMap<&'a Ident, Vec<(&'a Ident, &'a Span)>>,
+    stack: &mut Vec<&'a Ident>,
+    done: &mut HashSet<&'a Ident>,
+    errors: &mut Vec<(Span, String)>,
+) {
+    let shard = *stack.last().unwrap();
+    if !done.insert(shard) {
+        return;
+    }
+    for &(to, span) in edges.get(shard).into_iter().flatten() {
+        if stack.contains(&to) {
+            let message = format!(
+                "cycle detected when importing shard `{}` into shard `{}`",
+                to, shard
+            );
+            errors.push((span.clone(), message));
+        } else {
+            stack.push(to);
+            visit_shard(edges, stack, done, errors);
+            stack.pop();
+        }
+    }
+}
+
+fn kind_name(kind: VariantKind) -> &'static str {
+    match kind {
+        VariantKind::Unit => "unit",
+        VariantKind::Tuple => "tuple",
+        VariantKind::Struct => "struct",
+    }
+}
+
+// The constructor matched by a literal pattern.
+fn literal_ctor(expr: &Expr) -> Ctor {
+    match &expr.kind {
+        ExprKind::Literal(Literal::Int(i, _)) => Ctor::Int(*i as i128),
+        ExprKind::Literal(Literal::Bool(b)) => Ctor::Bool(*b),
+        ExprKind::UnOp(UnOp::Neg, lit) => match literal_ctor(lit) {
+            Ctor::Int(i) => Ctor::Int(-i),
+            ctor => ctor,
+        },
+        _ => unreachable!("literal pattern is not a literal"),
+    }
+}
+
+// The result type of `op` applied to two operands of type `typ`, if allowed.
+fn binop_type(op: BinOp, typ: &Type) -> Option<Type> {
+    match (op, typ.unalias()) {
+        (
+            BinOp::Add | BinOp::Minus | BinOp::Times | BinOp::Divide,
+            Type::Int(_) | Type::Float(_),
+        )
+        | (BinOp::Rem | BinOp::Shl | BinOp::Shr, Type::Int(_))
+        | (BinOp::BitAnd | BinOp::BitOr | BinOp::BitXor, Type::Int(_) | Type::Bool)
+        | (BinOp::And | BinOp::Or, Type::Bool) => Some(typ.clone()),
+        (BinOp::Eq | BinOp::Ne, Type::Int(_) | Type::Float(_) | Type::Bool)
+        | (BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge, Type::Int(_) | Type::Float(_)) => {
+            Some(Type::Bool)
+        }
+        _ => None,
+    }
+}
+
+// The type of a value that is either of type `a` or `b`, if any.
+fn join(a: &Type, b: &Type) -> Option<Type> {
+    match (a, b) {
+        (Type::Never, t) | (t, Type::Never) => Some(t.clone()),
+        _ if a == b => Some(a.clone()),
+        _ => None,
+    }
+}
+
+fn is_unsuffixed_literal(expr: &Expr) -> bool {
+    matches!(
+        expr.kind,
+        ExprKind::Literal(Literal::Int(_, None) | Literal::Float(_, None))
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::token_kind::TokenKind;
+    use logos::Logos;
+
+    // Type checks `src` as a program of one module, and returns its errors
+    // and warnings as they are reported.
+    fn diagnostics(src: &str) -> Vec<String> {
+        let (items, parse_errors) = Parser::new(TokenKind::lexer(src)).parse_items();
+        assert!(parse_errors.is_empty(), "{:?}", parse_errors);
+        let root = Module {
+            path: Ident::new(),
+            items: 0..items.len(),
+            shard: None,
+            imports: Vec::new(),
+            consts: Vec::new(),
+            externs: Vec::new(),
+        };
+        let mut program = Program {
+            items,
+            modules: vec![root],
+        };
+        check(&mut program)
+            .iter()
+            .map(|err| err.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn variables() {
+        let src = "const K: int = 3\nfn main() -> int {\n    let x = 5\n    let f = (a: int) => a + K\n    x + f(1) + K\n}\n";
+        assert_eq!(diagnostics(src), Vec::<String>::new());
+        let src =
+            "fn main() -> int {\n    let x = 5\n    let f = (a: int) => a + x\n    x + y\n}\n";
+        assert_eq!(
+            diagnostics(src),
+            [
+                "cannot capture local `x` in a function",
+                "cannot find value `y` in this scope",
+            ]
+        );
+    }
+
+    #[test]
+    fn calls() {
+        let src = "fn add(a: int, b: int) -> int { a + b }\nfn main() -> int {\n    let x = 1\n    add(1) + add(1, 2.0) + x(1)\n}\n";
+        assert_eq!(
+            diagnostics(src),
+            [
+                "this function takes 2 arguments but 1 was supplied",
+                "expected `int`, found `float`",
+                "expected function, found `int`",
+            ]
+        );
+    }
+
+    #[test]
+    fn operators() {
+        let src = "fn main() -> int {\n    let a = 1 + 2 * 3 << 1 & 7 | 8 ^ 2 % 3 >> 1\n    let b = !(a < 3 && a >= 1 || a != 2) == (-1.5 > 0.0)\n    if b { !a } else { -a }\n}\n";
+        assert_eq!(diagnostics(src), Vec::<String>::new());
+        let src = "fn main() -> int {\n    let a = 1 + 2.0\n    let b = true < false\n    let c = 1.5 % 2.0\n    let d = -true\n    if 1 { 1 } else { 2 }\n}\n";
+        assert_eq!(
+            diagnostics(src),
+            [
+                "cannot apply `+` to `int` and `float`",
+                "cannot apply `<` to `bool` and `bool`",
+                "cannot apply `%` to `float` and `float`",
+                "cannot apply unary `-` to `bool`",
+                "expected `bool`, found `int`",
+            ]
+        );
+    }
+
+    #[test]
+    fn if_branches() {
+        // Branches only need to agree when the value is used.
+        let src = "fn main() -> int {\n    let x = 2\n    if x > 1 { 1.0 } else { true }\n    let y = if x > 1 { 1 } else if x < 0 { 2 } else { 3 }\n    if x > 1 { return 1 } else { y }\n}\n";
+        assert_eq!(diagnostics(src), Vec::<String>::new());
+        let src = "fn main() -> int {\n    let x = if true { 1 } else { 2.0 }\n    let y: int = if true { 1 }\n    y\n}\n";
+        assert_eq!(
+            diagnostics(src),
+            [
+                "`if` and `else` have incompatible types: `int` and `float`",
+                "`if` without `else` has type `()`, but its branch has type `int`",
+            ]
+        );
+    }
+
+    #[test]
+    fn loops() {
+        let src = "fn main() -> int {\n    let mut n = 0\n    'outer: for i in 0..10 {\n        while n < i {\n            if n == 5 { continue 'outer }\n            n += 1\n        }\n        if i == 8 { break 'outer }\n    }\n    loop { break n * 2 }\n}\n";
+        assert_eq!(diagnostics(src), Vec::<String>::new());
+        let src = "fn main() -> int {\n    let w: float = loop { break 1 }\n    while true { break 1 }\n    if w > 0.0 { break }\n    if w < 0.0 { continue 'nope }\n    for i in 0..2.0 {}\n    0\n}\n";
+        assert_eq!(
+            diagnostics(src),
+            [
+                "expected `float`, found `int`",
+                "`break` with value from a `while` loop",
+                "`break` outside of a loop",
+                "use of undeclared label `'nope`",
+                "cannot iterate over a range from `int` to `float`",
+            ]
+        );
+    }
+
+    #[test]
+    fn returns() {
+        let src = "fn f(x: int) -> int {\n    if x > 0 { return 1.5 }\n    x\n}\nfn main() -> int {\n    let g = (a: int) => { if a > 0 { return a } 0 }\n    let h = (a: int) -> float => { return a }\n    let k = (a: int) => { if a > 0 { return 1.5 } a }\n    return g(1)\n    let y = 3\n    y\n}\n";
+        // Only the first statement after `return` is flagged.
+        assert_eq!(
+            diagnostics(src),
+            [
+                "expected `int`, found `float`",
+                "expected `float`, found `int`",
+                "expected `float`, found `int`",
+                "warning: unreachable statement",
+            ]
+        );
+    }
+
+    #[test]
+    fn assignments() {
+        let src = "struct P { x: int }\nfn main() -> int {\n    let mut a = 1\n    a = 2\n    a += 3\n    let mut p = P { x: 1 }\n    p.x *= 2\n    a + p.x\n}\n";
+        assert_eq!(diagnostics(src), Vec::<String>::new());
+        let src = "const K: int = 1\nstruct P { x: int }\nfn main() -> int {\n    let mut a = 1\n    let b = 1\n    b -= 1\n    let q = P { x: 1 }\n    q.x = 2\n    K = 3\n    a += 1.5\n    a = true\n    let f = (n: int) => { n = 1; n }\n    a\n}\n";
+        assert_eq!(
+            diagnostics(src),
+            [
+                "cannot assign to immutable binding `b`",
+                "cannot assign to immutable binding `q`",
+                "cannot assign to immutable binding `K`",
+                "cannot apply `+=` to `int` and `float`",
+                "expected `int`, found `bool`",
+                "cannot assign to immutable binding `n`",
+            ]
+        );
+    }
+
+    #[test]
+    fn annotations() {
+        let src = "fn main() -> int {\n    let x: float = 1.0\n    let y: i32 = 7\n    let f = (a: int) -> int => a * 2\n    let h: fn(int) -> int = f\n    let t: (int, float) = (1, x)\n    h(t.0)\n}\n";
+        assert_eq!(diagnostics(src), Vec::<String>::new());
+        let src = "fn main() -> int {\n    let x: float = 1\n    let y: i32 = 7\n    let z: Foo = 1\n    let g = (a: int) -> float => a\n    let u: bool = y\n    let f = (a: int) -> int => a\n    f(y)\n}\n";
+        assert_eq!(
+            diagnostics(src),
+            [
+                "expected `float`, found `int`",
+                "cannot find type `Foo`",
+                "expected `float`, found `int`",
+                "expected `bool`, found `i32`",
+                "expected `int`, found `i32`",
+            ]
+        );
+    }
+
+    #[test]
+    fn fn_items() {
+        let src = "fn even(n: int) -> bool { if n == 0 { true } else { odd(n - 1) } }\nfn main() -> int { if even(10) { 1 } else { 0 } }\nfn odd(n: int) -> bool { if n == 0 { false } else { even(n - 1) } }\n";
+        assert_eq!(diagnostics(src), Vec::<String>::new());
+        let src = "fn main(x: int) -> int { x }\nfn f() {}\nfn f(x: int) {}\n";
+        assert_eq!(
+            diagnostics(src),
+            [
+                "the name `f` is defined multiple times",
+                "`main` function takes no arguments",
+            ]
+        );
+        assert_eq!(diagnostics("fn f() {}\n"), ["`main` function not found"]);
+    }
+
+    #[test]
+    fn many_items() {
+        let src = "extern fn log(x: int)\nconst BASE: int = 10\nstruct Point { x: int, y: int }\nenum Shape { Dot(Point), Empty }\ntype Coord = int\nimpl Point { fn sum(self) -> Coord { self.x + self.y } }\nfn main() -> int {\n    let p = Point { x: BASE, y: 2 }\n    log(p.sum())\n    match Shape::Dot(p) { Shape::Dot(q) => q.x, Shape::Empty => 0 }\n}\n";
+        assert_eq!(diagnostics(src), Vec::<String>::new());
+        let src = format!("{}struct Point {{ z: int }}\n", src);
+        assert_eq!(
+            diagnostics(&src),
+            ["the type `Point` is defined multiple times"]
+        );
+    }
+
+    #[test]
+    fn aliases() {
+        let src = "type Meters = float\ntype Pair<T> = (T, T)\nfn main() -> int {\n    let m: Meters = 1.5\n    let p: Pair<int> = (1, 2)\n    let q: Pair<Meters> = (m, 2.0)\n    p.0\n}\n";
+        assert_eq!(diagnostics(src), Vec::<String>::new());
+        // Diagnostics name the alias rather than the type it stands for.
+        let src = "type Meters = float\ntype Pair<T> = (T, T)\ntype Loop = Loop\ntype Bad = Pair<int, int>\nfn f(x: Meters) -> int { x }\nfn g(p: Pair<int>) -> Pair<Meters> { p }\nfn main() -> int { 0 }\n";
+        assert_eq!(
+            diagnostics(src),
+            [
+                "cycle detected when expanding type alias `Loop`",
+                "type alias `Pair` takes 1 generic argument but 2 were supplied",
+                "expected `int`, found `Meters`",
+                "expected `Pair<Meters>`, found `Pair<int>`",
+            ]
+        );
+    }
+
+    #[test]
+    fn type_defined_twice() {
+        for src in [
+            "type C = int\nenum C { A }\n",
+            "enum C { A }\ntype C = int\n",
+        ] {
+            let src = format!("{}fn main() -> int {{ 0 }}\n", src);
+            assert_eq!(
+                diagnostics(&src),
+                ["the type `C` is defined multiple times"]
+            );
+        }
+    }
+
+    #[test]
+    fn methods() {
+        let decls = "struct Point { x: float, y: float }\nimpl Point {\n    fn new(x: float, y: float) -> Point { Point { x, y } }\n    fn len(self) -> float { self.x * self.x + self.y * self.y }\n    fn scale(self, k: float) -> Point { Point::new(self.x * k, self.y * k) }\n}\n";
+        let src = format!("{}fn main() -> int {{\n    let p = Point::new(3.0, 4.0)\n    let a = p.scale(2.0).len()\n    0\n}}\n", decls);
+        assert_eq!(diagnostics(&src), Vec::<String>::new());
+        let src = format!("{}fn main() -> int {{\n    let p = Point::new(3.0, 4.0)\n    let a = p.new(1.0, 2.0)\n    let b = p.area()\n    let c = Point::area()\n    let d = p.len(1.0)\n    0\n}}\n", decls);
+        assert_eq!(
+            diagnostics(&src),
+            [
+                "`Point::new` is an associated function, not a method",
+                "no method named `area` on type `Point`",
+                "no function named `area` in `Point`",
+                "this method takes 0 arguments but 1 was supplied",
+            ]
+        );
+    }
+}
@@ -0,0 +1,275 @@
+use super::ast::{FloatTy, Ident, IntTy};
+use super::wasm;
+use std::fmt;
+use std::rc::Rc;
+
+#[derive(Debug, Clone)]
+pub enum Type {
+    Int(IntTy),
+    Float(FloatTy),
+    Bool,
+    String,
+    Unit,
+    // The type of expressions that never produce a value, like `break`.
+    Never,
+    Fn(Vec<Type>, Box<Type>),
+    Struct(Rc<StructType>),
+    Enum(Rc<EnumType>),
+    // Laid out like a struct whose fields are named by their position.
+    Tuple(Rc<StructType>),
+    // A type alias, kept so that diagnostics can show the type by the name
+    // it was written with.
+    Alias(Rc<AliasType>),
+    // Type of an expression that failed to check. It is compatible with
+    // every other type, so a mistake is only reported once.
+    Error,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct StructType {
+    pub name: Ident,
+    pub fields: Vec<(Ident, Type)>,
+}
+
+impl StructType {
+    pub fn tuple(types: Vec<Type>) -> StructType {
+        let fields = types.into_iter().enumerate();
+        StructType {
+            name: Ident::new(),
+            fields: fields.map(|(i, typ)| (i.to_string(), typ)).collect(),
+        }
+    }
+
+    // The offset and type of the field `name`. Fields are laid out in
+    // declaration order, each at a multiple of its alignment.
+    pub fn field(&self, name: &str) -> Option<(u32, &Type)> {
+        let mut offset: u32 = 0;
+        for (field, typ) in &self.fields {
+            offset = offset.next_multiple_of(typ.align());
+            if field == name {
+                return Some((offset, typ));
+            }
+            offset += typ.size();
+        }
+        None
+    }
+
+    pub fn size(&self) -> u32 {
+        let end = self.fields.iter().fold(0, |offset: u32, (_, typ)| {
+            offset.next_multiple_of(typ.align()) + typ.size()
+        });
+        end.next_multiple_of(self.align())
+    }
+
+    pub fn align(&self) -> u32 {
+        self.fields
+            .iter()
+            .map(|(_, typ)| typ.align())
+            .max()
+            .unwrap_or(1)
+    }
+}
+
+// Enum values are a `u32` tag, the index of their variant, followed by the
+// variant's fields laid out like those of a struct.
+#[derive(Debug, PartialEq)]
+pub struct EnumType {
+    pub name: Ident,
+    pub variants: Vec<VariantType>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct VariantType {
+    pub name: Ident,
+    pub kind: VariantKind,
+    // The fields of a tuple variant are named by their position.
+    pub payload: StructType,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VariantKind {
+    Unit,
+    Tuple,
+    Struct,
+}
+
+impl EnumType {
+    // The tag and the type of the variant `name`.
+    pub fn variant(&self, name: &str) -> Option<(u32, &VariantType)> {
+        let index = self.variants.iter().position(|v| v.name == name)?;
+        Some((index as u32, &self.variants[index]))
+    }
+
+    // The offset of the payload of every variant.
+    pub fn payload_offset(&self) -> u32 {
+        4u32.next_multiple_of(self.payload_align())
+    }
+
+    fn payload_align(&self) -> u32 {
+        let aligns = self.variants.iter().map(|v| v.payload.align());
+        aligns.max().unwrap_or(1)
+    }
+
+    pub fn size(&self) -> u32 {
+        let payload = self.variants.iter().map(|v| v.payload.size());
+        let end = self.payload_offset() + payload.max().unwrap_or(0);
+        end.next_multiple_of(self.align())
+    }
+
+    pub fn align(&self) -> u32 {
+        self.payload_align().max(4)
+    }
+}
+
+// `Name<args>`, standing for `typ`.
+#[derive(Debug, PartialEq)]
+pub struct AliasType {
+    pub name: Ident,
+    pub args: Vec<Type>,
+    pub typ: Type,
+}
+
+impl Type {
+    pub fn from_name(name: &str) -> Option<Type> {
+        let typ = match name {
+            "int" | "i64" => Type::Int(IntTy::I64),
+            "i8" => Type::Int(IntTy::I8),
+            "i16" => Type::Int(IntTy::I16),
+            "i32" => Type::Int(IntTy::I32),
+            "u8" => Type::Int(IntTy::U8),
+            "u16" => Type::Int(IntTy::U16),
+            "u32" => Type::Int(IntTy::U32),
+            "u64" => Type::Int(IntTy::U64),
+            "float" | "f64" => Type::Float(FloatTy::F64),
+            "f32" => Type::Float(FloatTy::F32),
+            "bool" => Type::Bool,
+            "string" => Type::String,
+            _ => return None,
+        };
+        Some(typ)
+    }
+
+    // The type an alias stands for, through any number of aliases.
+    pub fn unalias(&self) -> &Type {
+        match self {
+            Type::Alias(alias) => alias.typ.unalias(),
+            typ => typ,
+        }
+    }
+
+    // The wasm values a value of this type is represented by.
+    pub fn valtypes(&self) -> Vec<wasm::ValType> {
+        let num = |t| vec![wasm::ValType::NumType(t)];
+        match self {
+            Type::Int(IntTy::I64 | IntTy::U64) => num(wasm::NumType::I64),
+            Type::Int(_) | Type::Bool => num(wasm::NumType::I32),
+            Type::Float(FloatTy::F32) => num(wasm::NumType::F32),
+            Type::Float(FloatTy::F64) => num(wasm::NumType::F64),
+            // Strings are a pointer and a length into linear memory.
+            Type::String => vec![
+                wasm::ValType::NumType(wasm::NumType::I32),
+                wasm::ValType::NumType(wasm::NumType::I32),
+            ],
+            // Function values are indices into the module's function table.
+            Type::Fn(_, _) => num(wasm::NumType::I32),
+            // Structs, enums and tuples live in linear memory and are passed
+            // by address.
+            Type::Struct(_) | Type::Enum(_) | Type::Tuple(_) => num(wasm::NumType::I32),
+            Type::Alias(alias) => alias.typ.valtypes(),
+            Type::Unit | Type::Never | Type::Error => Vec::new(),
+        }
+    }
+
+    // The number of bytes a value of this type takes in linear memory.
+    // Structs, enums and tuples nested in other structs are stored inline.
+    pub fn size(&self) -> u32 {
+        match self {
+            Type::Int(IntTy::I8 | IntTy::U8) | Type::Bool => 1,
+            Type::Int(IntTy::I16 | IntTy::U16) => 2,
+            Type::Int(IntTy::I32 | IntTy::U32) | Type::Float(FloatTy::F32) | Type::Fn(_, _) => 4,
+            Type::Int(IntTy::I64 | IntTy::U64) | Type::Float(FloatTy::F64) | Type::String => 8,
+            Type::Struct(st) | Type::Tuple(st) => st.size(),
+            Type::Enum(en) => en.size(),
+            Type::Alias(alias) => alias.typ.size(),
+            Type::Unit | Type::Never | Type::Error => 0,
+        }
+    }
+
+    pub fn align(&self) -> u32 {
+        match self {
+            Type::String => 4,
+            Type::Struct(st) | Type::Tuple(st) => st.align(),
+            Type::Enum(en) => en.align(),
+            Type::Alias(alias) => alias.typ.align(),
+            Type::Unit | Type::Never | Type::Error => 1,
+            _ => self.size(),
+        }
+    }
+}
+
+// Aliases are equal to the types they stand for.
+impl PartialEq for Type {
+    fn eq(&self, other: &Type) -> bool {
+        match (self.unalias(), other.unalias()) {
+            (Type::Int(a), Type::Int(b)) => a == b,
+            (Type::Float(a), Type::Float(b)) => a == b,
+            (Type::Fn(a, ret_a), Type::Fn(b, ret_b)) => a == b && ret_a == ret_b,
+            (Type::Struct(a), Type::Struct(b)) | (Type::Tuple(a), Type::Tuple(b)) => a == b,
+            (Type::Enum(a), Type::Enum(b)) => a == b,
+            (Type::Bool, Type::Bool)
+            | (Type::String, Type::String)
+            | (Type::Unit, Type::Unit)
+            | (Type::Never, Type::Never)
+            | (Type::Error, Type::Error) => true,
+            _ => false,
+        }
+    }
+}
+
+fn write_list(f: &mut fmt::Formatter, types: &[Type]) -> fmt::Result {
+    for (i, typ) in types.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{}", typ)?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Type::Int(IntTy::I64) => write!(f, "int"),
+            Type::Int(t) => write!(f, "{}", format!("{:?}", t).to_lowercase()),
+            Type::Float(FloatTy::F64) => write!(f, "float"),
+            Type::Float(FloatTy::F32) => write!(f, "f32"),
+            Type::Bool => write!(f, "bool"),
+            Type::String => write!(f, "string"),
+            Type::Unit => write!(f, "()"),
+            Type::Never => write!(f, "!"),
+            Type::Fn(args, ret) => {
+                write!(f, "fn(")?;
+                write_list(f, args)?;
+                write!(f, ") -> {}", ret)
+            }
+            Type::Struct(st) => write!(f, "{}", st.name),
+            Type::Enum(en) => write!(f, "{}", en.name),
+            Type::Tuple(st) => {
+                let types: Vec<Type> = st.fields.iter().map(|(_, t)| t.clone()).collect();
+                write!(f, "(")?;
+                write_list(f, &types)?;
+                match types.len() {
+                    1 => write!(f, ",)"),
+                    _ => write!(f, ")"),
+                }
+            }
+            Type::Alias(alias) if alias.args.is_empty() => write!(f, "{}", alias.name),
+            Type::Alias(alias) => {
+                write!(f, "{}<", alias.name)?;
+                write_list(f, &alias.args)?;
+                write!(f, ">")
+            }
+            Type::Error => write!(f, "{{error}}"),
+        }
+    }
+}
@@ -40,7 +40,7 @@ impl WriteContext<Instr> for WASMBinary {
                     write_bin!(w << instr)?;
                 }
                 if else_instrs.len() > 0 {
-                    write_bin!(w << 0x0Bu8)?;
+                    write_bin!(w << 0x05u8)?;
                     for instr in else_instrs {
                         write_bin!(w << instr)?;
                     }
@@ -52,7 +52,7 @@ impl WriteContext<Instr> for WASMBinary {
             Instr::BrTable(v, l) => write_bin!(w << 0x0Eu8 << v << l),
             Instr::Return => write_bin!(w << 0x0Fu8),
             Instr::Call(x) => write_bin!(w << 0x10u8 << x),
-            Instr::CallIndirect(y, x) => write_bin!(w << 0x11u8 << y << x),
+            Instr::CallIndirect(x, y) => write_bin!(w << 0x11u8 << y << x),
 
             // Reference Instrunctions ---------------------------
             Instr::RefNull(t) => write_bin!(w << 0xD0u8 << t),
@@ -115,8 +115,8 @@ impl WriteContext<Instr> for WASMBinary {
             Instr::MemoryFill => write_bin!(w << 0xFCu8 << 11u32 << 0x00u8),
 
             // Numeric Instructions ------------------------------
-            Instr::I32Const(n) => write_bin!(w << 0x41u8 << n),
-            Instr::I64Const(n) => write_bin!(w << 0x42u8 << n),
+            Instr::I32Const(n) => write_bin!(w << 0x41u8 << (*n as S32)),
+            Instr::I64Const(n) => write_bin!(w << 0x42u8 << (*n as S64)),
             Instr::F32Const(z) => write_bin!(w << 0x43u8 << z),
             Instr::F64Const(z) => write_bin!(w << 0x44u8 << z),
 
@@ -1,7 +1,7 @@
 use super::{WASMBinary, WriteContext};
 use crate::wasm::instr::Instr;
 use crate::wasm::module::*;
-use crate::wasm::typ::RefType;
+use crate::wasm::typ::{RefType, ValType};
 use std::io;
 
 fn write_section<W>(w: &mut W, n: u8, input: Vec<u8>) -> io::Result<()>
@@ -216,21 +216,17 @@ fn code_section(module: &Module) -> io::Result<Vec<Vec<u8>>> {
     for f in &module.funcs {
         let mut codevec = Vec::new();
 
-        if f.locals.len() > 0 {
-            let mut prev_local = &f.locals[0];
-            let mut count: u32 = 0;
-            for local in &f.locals {
-                if local == prev_local {
-                    count += 1;
-                } else {
-                    WASMBinary::write(&mut codevec, &count)?;
-                    WASMBinary::write(&mut codevec, &prev_local)?;
-                    prev_local = local;
-                    count = 1;
-                }
+        let mut groups: Vec<(u32, &ValType)> = Vec::new();
+        for local in &f.locals {
+            match groups.last_mut() {
+                Some((count, prev_local)) if *prev_local == local => *count += 1,
+                _ => groups.push((1, local)),
             }
-            WASMBinary::write(&mut codevec, &count)?;
-            WASMBinary::write(&mut codevec, &prev_local)?;
+        }
+        WASMBinary::write(&mut codevec, &groups.len())?;
+        for (count, local) in &groups {
+            WASMBinary::write(&mut codevec, count)?;
+            WASMBinary::write(&mut codevec, local)?;
         }
 
         WASMBinary::write(&mut codevec, &f.body)?;
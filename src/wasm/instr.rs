@@ -6,6 +6,7 @@ use super::value::*;
 
 // https://webassembly.github.io/spec/core/syntax/instructions.html
 
+#[derive(Clone)]
 pub enum Instr {
     // Const Instructions -----------------------
     I32Const(U32),
@@ -264,17 +265,20 @@ pub enum Instr {
     CallIndirect(TableIdx, TypeIdx),
 }
 
+#[derive(Clone)]
 pub struct MemArg {
     pub offset: U32,
     pub align: U32,
 }
 
+#[derive(Clone)]
 pub enum BlockType {
     TypeIdx(TypeIdx),
     ValType(ValType),
     None,
 }
 
+#[derive(Clone)]
 pub struct Expr {
     pub instrs: Vec<Instr>,
 }
@@ -29,6 +29,7 @@ pub type DataIdx = U32;
 pub type LocalIdx = U32;
 pub type LabelIdx = U32;
 
+#[derive(Clone)]
 pub struct Func {
     pub typ: TypeIdx,
     pub locals: Vec<ValType>,
@@ -43,6 +44,7 @@ pub struct Mem {
     pub typ: MemType,
 }
 
+#[derive(Clone)]
 pub struct Global {
     pub typ: GlobalType,
     pub init: Expr,
@@ -4,7 +4,7 @@ use super::value::*;
 
 // https://webassembly.github.io/spec/core/syntax/types.html
 
-#[derive(PartialEq)]
+#[derive(PartialEq, Clone, Copy, Debug)]
 pub enum NumType {
     I32,
     I64,
@@ -12,18 +12,18 @@ pub enum NumType {
     F64,
 }
 
-#[derive(PartialEq)]
+#[derive(PartialEq, Clone, Copy, Debug)]
 pub enum VecType {
     V128,
 }
 
-#[derive(PartialEq)]
+#[derive(PartialEq, Clone, Copy, Debug)]
 pub enum RefType {
     FuncRef,
     ExternRef,
 }
 
-#[derive(PartialEq)]
+#[derive(PartialEq, Clone, Copy, Debug)]
 pub enum ValType {
     NumType(NumType),
     VecType(VecType),
@@ -53,11 +53,13 @@ pub struct TableType {
     pub et: RefType,
 }
 
+#[derive(Clone)]
 pub struct GlobalType {
     pub mutability: Mut,
     pub typ: ValType,
 }
 
+#[derive(Clone)]
 pub enum Mut {
     Const,
     Var,